@@ -0,0 +1,234 @@
+use embedded_graphics::draw_target::DrawTarget;
+use embedded_graphics::Drawable;
+use embedded_graphics::geometry::{Point, Size};
+use embedded_graphics::pixelcolor::Rgb565;
+use embedded_graphics::prelude::Primitive;
+use embedded_graphics::primitives::{Circle, Line, PrimitiveStyle, Rectangle};
+use embedded_hal_async::delay::DelayNs;
+
+use crate::graphics::Theme;
+use crate::touch::{TouchController, TouchPhase};
+use crate::touch_mapper::{CalibrationMatrix, TouchPosMapper};
+
+const CROSSHAIR_SIZE: i32 = 10;
+const MAX_CALIBRATION_POINTS: usize = 5;
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum CalibrationPoints {
+    /// exact affine calibration
+    Three,
+    /// least squares fit over the four corners and the center
+    Five,
+}
+
+impl CalibrationPoints {
+    fn count(&self) -> usize {
+        match self {
+            CalibrationPoints::Three => 3,
+            CalibrationPoints::Five => 5,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum CalibrationError<DisplayError, TouchError> {
+    Display(DisplayError),
+    Touch(TouchError),
+    /// the samples were on one line, e.g. the same target was touched twice
+    InvalidSamples,
+}
+
+/// Full screen calibration, draws a crosshair per target and collects the raw
+/// samples of an unmapped touch controller
+pub struct CalibrationScreen {
+    width: u16,
+    height: u16,
+    margin: u16,
+    min_samples: u32,
+    poll_interval_ms: u32,
+    foreground_color: Rgb565,
+    background_color: Rgb565,
+}
+
+impl CalibrationScreen {
+    pub fn new(width: u16, height: u16, theme: &Theme) -> Self {
+        CalibrationScreen {
+            width,
+            height,
+            margin: width.min(height) / 10,
+            min_samples: 5,
+            poll_interval_ms: 10,
+            foreground_color: theme.text_color_primary,
+            background_color: theme.screen_background_color,
+        }
+    }
+
+    /// Distance of the outer targets to the display border
+    pub fn set_margin(&mut self, margin: u16) {
+        self.margin = margin.min(self.width.min(self.height) / 2);
+    }
+
+    /// Samples per target before a release is accepted
+    pub fn set_min_samples(&mut self, min_samples: u32) {
+        self.min_samples = min_samples.max(1);
+    }
+
+    pub fn set_poll_interval_ms(&mut self, poll_interval_ms: u32) {
+        self.poll_interval_ms = poll_interval_ms;
+    }
+
+    pub fn get_targets(&self, points: CalibrationPoints) -> [(u16, u16); MAX_CALIBRATION_POINTS] {
+        let (left, top) = (self.margin, self.margin);
+        let (right, bottom) = (self.width - self.margin, self.height - self.margin);
+        let center = (self.width / 2, self.height / 2);
+        match points {
+            CalibrationPoints::Three => [(left, top), (right, center.1), (center.0, bottom), (0, 0), (0, 0)],
+            CalibrationPoints::Five => [(left, top), (right, top), (right, bottom), (left, bottom), center],
+        }
+    }
+
+    fn draw_crosshair<D>(&self, display: &mut D, target: (u16, u16), color: Rgb565) -> Result<(), D::Error>
+        where D: DrawTarget<Color=Rgb565> {
+        let center = Point::new(target.0 as i32, target.1 as i32);
+        let style = PrimitiveStyle::with_stroke(color, 1);
+        Line::new(center - Point::new(CROSSHAIR_SIZE, 0), center + Point::new(CROSSHAIR_SIZE, 0))
+            .into_styled(style)
+            .draw(display)?;
+        Line::new(center - Point::new(0, CROSSHAIR_SIZE), center + Point::new(0, CROSSHAIR_SIZE))
+            .into_styled(style)
+            .draw(display)?;
+        Circle::with_center(center, CROSSHAIR_SIZE as u32)
+            .into_styled(style)
+            .draw(display)?;
+        Ok(())
+    }
+
+    /// Average raw position of one touch, returns after the release
+    async fn collect_sample<T, DL>(&self, controller: &mut T, delay: &mut DL) -> Result<(u16, u16), T::Error>
+        where T: TouchController, DL: DelayNs {
+        let mut sum = (0u32, 0u32);
+        let mut count = 0u32;
+        loop {
+            let points = controller.read_touches().await?;
+            match points[0] {
+                Some(sample) if sample.phase != TouchPhase::Up => {
+                    sum.0 += sample.x as u32;
+                    sum.1 += sample.y as u32;
+                    count += 1;
+                }
+                _ if count >= self.min_samples => {
+                    return Ok(((sum.0 / count) as u16, (sum.1 / count) as u16));
+                }
+                // released too early, start over
+                _ => {
+                    sum = (0, 0);
+                    count = 0;
+                }
+            }
+            delay.delay_ms(self.poll_interval_ms).await;
+        }
+    }
+
+    /// Clears the display, asks for a touch on every target and computes the mapper.
+    /// The controller must report raw positions, not a MappedTouchController
+    pub async fn run<D, T, DL>(&self, display: &mut D, controller: &mut T, delay: &mut DL,
+                               points: CalibrationPoints) -> Result<TouchPosMapper, CalibrationError<D::Error, T::Error>>
+        where D: DrawTarget<Color=Rgb565>, T: TouchController, DL: DelayNs {
+        let bounding_box = Rectangle::new(Point::zero(), Size::new(self.width as u32, self.height as u32));
+        display.fill_solid(&bounding_box, self.background_color).map_err(CalibrationError::Display)?;
+
+        let targets = self.get_targets(points);
+        let count = points.count();
+        let mut raw = [(0u16, 0u16); MAX_CALIBRATION_POINTS];
+        for index in 0..count {
+            self.draw_crosshair(display, targets[index], self.foreground_color).map_err(CalibrationError::Display)?;
+            raw[index] = self.collect_sample(controller, delay).await.map_err(CalibrationError::Touch)?;
+            self.draw_crosshair(display, targets[index], self.background_color).map_err(CalibrationError::Display)?;
+        }
+
+        CalibrationMatrix::from_points(&raw[..count], &targets[..count])
+            .and_then(|calibration| TouchPosMapper::try_new_calibrated(self.width, self.height, calibration))
+            .ok_or(CalibrationError::InvalidSamples)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate alloc;
+
+    use alloc::vec::Vec;
+
+    use crate::memory_display::MemoryDisplay;
+    use crate::mock_i2c::{block_on, NoDelay};
+    use crate::touch::TouchSample;
+    use crate::touch_mapper::Orientation;
+    use crate::touch_record::TouchReplay;
+
+    use super::*;
+
+    /// Rotated and skewed panel, raw = (200 + 14 x + 2 y, 300 + x + 11 y)
+    fn to_raw(target: (u16, u16)) -> (u16, u16) {
+        (200 + 14 * target.0 + 2 * target.1, 300 + target.0 + 11 * target.1)
+    }
+
+    /// Five samples and a release per target
+    fn touches(targets: &[(u16, u16)]) -> TouchReplay {
+        let mut samples = Vec::new();
+        for (index, target) in targets.iter().enumerate() {
+            let (x, y) = to_raw(*target);
+            for read in 0..6u64 {
+                let phase = match read {
+                    0 => TouchPhase::Down,
+                    5 => TouchPhase::Up,
+                    _ => TouchPhase::Move,
+                };
+                samples.push((index as u64 * 100 + read * 10, TouchSample { id: 0, x, y, pressure: None, phase }));
+            }
+        }
+        TouchReplay::new(samples)
+    }
+
+    #[test]
+    fn five_point_calibration_fits_a_skewed_panel() {
+        let theme = Theme::new_dark_theme();
+        let mut display = MemoryDisplay::new(240, 320);
+        let screen = CalibrationScreen::new(240, 320, &theme);
+        let targets = screen.get_targets(CalibrationPoints::Five);
+        let mut replay = touches(&targets);
+
+        let mapper = block_on(screen.run(&mut display, &mut replay, &mut NoDelay::default(), CalibrationPoints::Five))
+            .unwrap();
+        assert!(replay.is_finished());
+        for target in [(24u16, 24u16), (120, 160), (215, 295), (60, 250)] {
+            let (x, y) = to_raw(target);
+            // the mapper truncates, a fit of 23.99 maps to 23
+            let offset = mapper.map_touch_pos(x, y, Orientation::Deg0) - Point::new(target.0 as i32, target.1 as i32);
+            assert!(offset.x.abs() <= 1 && offset.y.abs() <= 1, "{:?} off by {:?}", target, offset);
+        }
+        // the crosshairs are erased after every target
+        assert_eq!(display.count_pixels(theme.text_color_primary), 0);
+    }
+
+    #[test]
+    fn three_point_calibration_is_exact() {
+        let display = [(24u16, 24u16), (216, 160), (120, 296)];
+        let raw = display.map(to_raw);
+        let calibration = CalibrationMatrix::from_three_points(&raw, &display).unwrap();
+        for (raw, display) in raw.iter().zip(display) {
+            let (x, y) = calibration.apply(raw.0, raw.1);
+            assert!((x - display.0 as f32).abs() < 0.01 && (y - display.1 as f32).abs() < 0.01);
+        }
+        assert!(calibration.is_valid());
+    }
+
+    #[test]
+    fn samples_on_one_line_are_rejected() {
+        let theme = Theme::new_dark_theme();
+        let mut display = MemoryDisplay::new(240, 320);
+        let screen = CalibrationScreen::new(240, 320, &theme);
+        let mut replay = touches(&[(24, 24), (24, 24), (24, 24)]);
+
+        let result = block_on(screen.run(&mut display, &mut replay, &mut NoDelay::default(), CalibrationPoints::Three));
+        assert_eq!(result.err(), Some(CalibrationError::InvalidSamples));
+    }
+}
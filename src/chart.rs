@@ -0,0 +1,552 @@
+extern crate alloc;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Write;
+
+use embedded_graphics::draw_target::DrawTarget;
+use embedded_graphics::Drawable;
+use embedded_graphics::geometry::{Point, Size};
+use embedded_graphics::mono_font::MonoTextStyle;
+use embedded_graphics::pixelcolor::Rgb565;
+use embedded_graphics::prelude::Primitive;
+use embedded_graphics::primitives::{Line, Polyline, PrimitiveStyle, Rectangle};
+use embedded_graphics::text::{Alignment, Baseline, TextStyle, TextStyleBuilder};
+
+use crate::graphics::{GraphicUtils, Theme};
+
+const CHART_TICK_LENGTH: i32 = 3;
+const CHART_TICK_LABEL_CHARS: u32 = 6;
+
+/// Fixed capacity buffer for streaming values, the oldest value is dropped
+/// when a new value is pushed into a full buffer
+#[derive(Clone, Debug)]
+pub struct RingBuffer<T> {
+    items: Vec<T>,
+    capacity: usize,
+    start: usize,
+}
+
+impl<T: Copy> RingBuffer<T> {
+    pub fn new(capacity: usize) -> Self {
+        RingBuffer {
+            items: Vec::with_capacity(capacity),
+            capacity: capacity.max(1),
+            start: 0,
+        }
+    }
+
+    pub fn push(&mut self, value: T) {
+        if self.items.len() < self.capacity {
+            self.items.push(value);
+        } else {
+            self.items[self.start] = value;
+            self.start = (self.start + 1) % self.capacity;
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.items.len() == self.capacity
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// index 0 is the oldest value
+    pub fn get(&self, index: usize) -> Option<T> {
+        if index < self.items.len() {
+            Some(self.items[(self.start + index) % self.items.len()])
+        } else {
+            None
+        }
+    }
+
+    pub fn last(&self) -> Option<T> {
+        if self.items.is_empty() {
+            None
+        } else {
+            self.get(self.items.len() - 1)
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item=T> + '_ {
+        (0..self.items.len()).map(move |index| self.items[(self.start + index) % self.items.len()])
+    }
+
+    pub fn clear(&mut self) {
+        self.items.clear();
+        self.start = 0;
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum AxisScale {
+    /// range follows the min and max of the visible values
+    Auto,
+    /// fixed min and max, values outside are clamped, a range with
+    /// min >= max is widened like an auto range of a single value
+    Fixed(f32, f32),
+}
+
+impl AxisScale {
+    fn get_range<I: Iterator<Item=f32>>(&self, values: I) -> (f32, f32) {
+        match self {
+            AxisScale::Fixed(min, max) if max > min => (*min, *max),
+            AxisScale::Fixed(min, _) => (*min - 1.0, *min + 1.0),
+            AxisScale::Auto => {
+                let mut range: Option<(f32, f32)> = None;
+                for value in values {
+                    range = match range {
+                        Some((min, max)) => Some((min.min(value), max.max(value))),
+                        None => Some((value, value)),
+                    };
+                }
+                match range {
+                    Some((min, max)) if max > min => (min, max),
+                    Some((min, _)) => (min - 1.0, min + 1.0),
+                    None => (0.0, 1.0),
+                }
+            }
+        }
+    }
+}
+
+/// Maps values into the pixel area of a chart
+#[derive(Copy, Clone, Debug)]
+struct PlotArea {
+    area: Rectangle,
+    min: f32,
+    max: f32,
+}
+
+impl PlotArea {
+    fn map_y(&self, value: f32) -> i32 {
+        let height = self.area.size.height.saturating_sub(1) as f32;
+        let value = value.max(self.min).min(self.max);
+        let offset = (value - self.min) / (self.max - self.min) * height;
+        self.area.top_left.y + (height - offset) as i32
+    }
+
+    fn map_x(&self, index: usize, count: usize) -> i32 {
+        let width = self.area.size.width.saturating_sub(1) as i32;
+        if count <= 1 {
+            return self.area.top_left.x;
+        }
+        self.area.top_left.x + index as i32 * width / (count - 1) as i32
+    }
+
+    fn get_points(&self, data: &RingBuffer<f32>) -> Vec<Point> {
+        data.iter()
+            .enumerate()
+            .map(|(index, value)| Point::new(self.map_x(index, data.capacity()), self.map_y(value)))
+            .collect()
+    }
+}
+
+fn get_tick_label(value: f32) -> String {
+    let mut label = String::new();
+    if value.abs() >= 100.0 {
+        let _ = write!(label, "{:.0}", value);
+    } else {
+        let _ = write!(label, "{:.1}", value);
+    }
+    label
+}
+
+fn get_tick_label_style() -> TextStyle {
+    TextStyleBuilder::new()
+        .alignment(Alignment::Right)
+        .baseline(Baseline::Middle)
+        .build()
+}
+
+/// Axes and tick labels shared by the line and bar chart
+struct Axes<'a> {
+    pos: Point,
+    size: Size,
+    tick_count: usize,
+    axis_color: Rgb565,
+    background_color: Rgb565,
+    character_style: MonoTextStyle<'a, Rgb565>,
+}
+
+impl<'a> Axes<'a> {
+    fn get_label_width(&self) -> u32 {
+        CHART_TICK_LABEL_CHARS * self.character_style.font.character_size.width
+    }
+
+    fn get_plot_area(&self, bottom_margin: u32, range: (f32, f32)) -> PlotArea {
+        let label_width = self.get_label_width() + CHART_TICK_LENGTH as u32 + 1;
+        let half_label_height = self.character_style.font.character_size.height / 2;
+        let top_left = Point::new(self.pos.x + label_width as i32 + 1, self.pos.y + half_label_height as i32);
+        let size = Size::new(self.size.width.saturating_sub(label_width + 1),
+                             self.size.height.saturating_sub(half_label_height + bottom_margin + 1));
+        PlotArea {
+            area: Rectangle::new(top_left, size),
+            min: range.0,
+            max: range.1,
+        }
+    }
+
+    fn draw<D>(&self, display: &mut D, plot: &PlotArea) -> Result<(), D::Error>
+        where D: DrawTarget<Color=Rgb565> {
+        display.fill_solid(&Rectangle::new(self.pos, self.size), self.background_color)?;
+
+        let axis_style = PrimitiveStyle::with_stroke(self.axis_color, 1);
+        let origin = Point::new(plot.area.top_left.x - 1, plot.area.top_left.y + plot.area.size.height as i32);
+        Line::new(Point::new(origin.x, plot.area.top_left.y), origin)
+            .into_styled(axis_style)
+            .draw(display)?;
+        Line::new(origin, Point::new(origin.x + plot.area.size.width as i32, origin.y))
+            .into_styled(axis_style)
+            .draw(display)?;
+
+        let mut label_character_style = self.character_style;
+        label_character_style.text_color = Some(self.axis_color);
+        let tick_count = self.tick_count.max(2);
+        for tick in 0..tick_count {
+            let value = plot.min + (plot.max - plot.min) * tick as f32 / (tick_count - 1) as f32;
+            let y = plot.map_y(value);
+            Line::new(Point::new(origin.x - CHART_TICK_LENGTH, y), Point::new(origin.x, y))
+                .into_styled(axis_style)
+                .draw(display)?;
+            if let Some(label) = GraphicUtils::get_text_with_ellipsis_checked(self.get_label_width(), get_tick_label(value).as_str(),
+                                                                              self.character_style.font) {
+                GraphicUtils::display_text(display, Point::new(origin.x - CHART_TICK_LENGTH - 1, y), label_character_style,
+                                           get_tick_label_style(), label.as_str())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+pub struct LineChart<'a> {
+    data: RingBuffer<f32>,
+    scale: AxisScale,
+    range: (f32, f32),
+    axes: Axes<'a>,
+    line_color: Rgb565,
+    visible_points: Vec<Point>,
+}
+
+impl<'a> LineChart<'a> {
+    pub fn new(capacity: usize, position: Point, size: Size, scale: AxisScale,
+               character_style: MonoTextStyle<'a, Rgb565>, theme: &Theme) -> Self {
+        LineChart {
+            data: RingBuffer::new(capacity),
+            scale,
+            range: scale.get_range(core::iter::empty()),
+            axes: Axes {
+                pos: position,
+                size,
+                tick_count: 5,
+                axis_color: theme.text_color_primary,
+                background_color: theme.screen_background_color,
+                character_style,
+            },
+            line_color: theme.button_background_color,
+            visible_points: Vec::new(),
+        }
+    }
+
+    pub fn set_tick_count(&mut self, tick_count: usize) {
+        self.axes.tick_count = tick_count;
+    }
+
+    pub fn get_data(&self) -> &RingBuffer<f32> {
+        &self.data
+    }
+
+    pub fn get_bounding_box(&self) -> Rectangle {
+        Rectangle::new(self.axes.pos, self.axes.size)
+    }
+
+    fn get_plot_area(&self) -> PlotArea {
+        self.axes.get_plot_area(CHART_TICK_LENGTH as u32, self.range)
+    }
+
+    pub fn draw<D>(&mut self, display: &mut D) -> Result<(), D::Error>
+        where D: DrawTarget<Color=Rgb565> {
+        self.range = self.scale.get_range(self.data.iter());
+        let plot = self.get_plot_area();
+        self.axes.draw(display, &plot)?;
+        self.visible_points.clear();
+        self.draw_plot(display, &plot)
+    }
+
+    /// Adds a value and scrolls the plot, axes and tick labels are only
+    /// redrawn if the auto scaled range changed
+    pub fn push<D>(&mut self, display: &mut D, value: f32) -> Result<(), D::Error>
+        where D: DrawTarget<Color=Rgb565> {
+        self.data.push(value);
+        if self.scale.get_range(self.data.iter()) != self.range {
+            return self.draw(display);
+        }
+        let plot = self.get_plot_area();
+        self.draw_plot(display, &plot)
+    }
+
+    fn draw_plot<D>(&mut self, display: &mut D, plot: &PlotArea) -> Result<(), D::Error>
+        where D: DrawTarget<Color=Rgb565> {
+        if self.visible_points.len() > 1 {
+            Polyline::new(&self.visible_points)
+                .into_styled(PrimitiveStyle::with_stroke(self.axes.background_color, 1))
+                .draw(display)?;
+        }
+        self.visible_points = plot.get_points(&self.data);
+        if self.visible_points.len() > 1 {
+            Polyline::new(&self.visible_points)
+                .into_styled(PrimitiveStyle::with_stroke(self.line_color, 1))
+                .draw(display)?;
+        }
+        Ok(())
+    }
+}
+
+pub struct BarChart<'a> {
+    values: Vec<f32>,
+    labels: Vec<String>,
+    scale: AxisScale,
+    range: (f32, f32),
+    axes: Axes<'a>,
+    bar_color: Rgb565,
+}
+
+impl<'a> BarChart<'a> {
+    pub fn new(labels: &[&str], position: Point, size: Size, scale: AxisScale,
+               character_style: MonoTextStyle<'a, Rgb565>, theme: &Theme) -> Self {
+        BarChart {
+            values: alloc::vec![0.0; labels.len()],
+            labels: labels.iter().map(|l| String::from(*l)).collect(),
+            scale,
+            range: scale.get_range(core::iter::empty()),
+            axes: Axes {
+                pos: position,
+                size,
+                tick_count: 5,
+                axis_color: theme.text_color_primary,
+                background_color: theme.screen_background_color,
+                character_style,
+            },
+            bar_color: theme.button_background_color,
+        }
+    }
+
+    pub fn set_tick_count(&mut self, tick_count: usize) {
+        self.axes.tick_count = tick_count;
+    }
+
+    pub fn get_bounding_box(&self) -> Rectangle {
+        Rectangle::new(self.axes.pos, self.axes.size)
+    }
+
+    fn get_plot_area(&self) -> PlotArea {
+        let label_height = self.axes.character_style.font.character_size.height;
+        self.axes.get_plot_area(label_height + 2, self.range)
+    }
+
+    fn get_bar_area(&self, plot: &PlotArea, index: usize) -> Rectangle {
+        let count = self.values.len().max(1) as u32;
+        let slot_width = plot.area.size.width / count;
+        let x = plot.area.top_left.x + (index as u32 * slot_width) as i32;
+        Rectangle::new(Point::new(x, plot.area.top_left.y), Size::new(slot_width, plot.area.size.height))
+    }
+
+    pub fn draw<D>(&mut self, display: &mut D) -> Result<(), D::Error>
+        where D: DrawTarget<Color=Rgb565> {
+        self.range = self.scale.get_range(self.values.iter().copied().chain(core::iter::once(0.0)));
+        let plot = self.get_plot_area();
+        self.axes.draw(display, &plot)?;
+
+        let label_style = TextStyleBuilder::new()
+            .alignment(Alignment::Center)
+            .baseline(Baseline::Top)
+            .build();
+        let mut label_character_style = self.axes.character_style;
+        label_character_style.text_color = Some(self.axes.axis_color);
+        for index in 0..self.values.len() {
+            self.draw_bar(display, &plot, index)?;
+            let bar_area = self.get_bar_area(&plot, index);
+            if let Some(label) = GraphicUtils::get_text_with_ellipsis_checked(bar_area.size.width, self.labels[index].as_str(),
+                                                                              self.axes.character_style.font) {
+                let label_pos = Point::new(bar_area.center().x, plot.area.top_left.y + plot.area.size.height as i32 + 2);
+                GraphicUtils::display_text(display, label_pos, label_character_style, label_style, label.as_str())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Updates a single bar, the other bars are only redrawn if the auto
+    /// scaled range changed
+    pub fn set_value<D>(&mut self, display: &mut D, index: usize, value: f32) -> Result<(), D::Error>
+        where D: DrawTarget<Color=Rgb565> {
+        if index >= self.values.len() {
+            return Ok(());
+        }
+        self.values[index] = value;
+        if self.scale.get_range(self.values.iter().copied().chain(core::iter::once(0.0))) != self.range {
+            return self.draw(display);
+        }
+        let plot = self.get_plot_area();
+        self.draw_bar(display, &plot, index)
+    }
+
+    fn draw_bar<D>(&self, display: &mut D, plot: &PlotArea, index: usize) -> Result<(), D::Error>
+        where D: DrawTarget<Color=Rgb565> {
+        let bar_area = self.get_bar_area(plot, index);
+        display.fill_solid(&bar_area, self.axes.background_color)?;
+
+        let zero_y = plot.map_y(0.0);
+        let value_y = plot.map_y(self.values[index]);
+        let bar_margin = bar_area.size.width / 8;
+        let top = zero_y.min(value_y);
+        let height = (zero_y - value_y).unsigned_abs() + 1;
+        let bar = Rectangle::new(Point::new(bar_area.top_left.x + bar_margin as i32, top),
+                                 Size::new(bar_area.size.width.saturating_sub(2 * bar_margin), height));
+        display.fill_solid(&bar, self.bar_color)
+    }
+}
+
+/// Small line plot without axes, e.g. for a status bar
+pub struct Sparkline {
+    data: RingBuffer<f32>,
+    scale: AxisScale,
+    range: (f32, f32),
+    pos: Point,
+    size: Size,
+    line_color: Rgb565,
+    background_color: Rgb565,
+    visible_points: Vec<Point>,
+}
+
+impl Sparkline {
+    pub fn new(capacity: usize, position: Point, size: Size, scale: AxisScale, theme: &Theme) -> Self {
+        Sparkline {
+            data: RingBuffer::new(capacity),
+            scale,
+            range: scale.get_range(core::iter::empty()),
+            pos: position,
+            size,
+            line_color: theme.text_color_primary,
+            background_color: theme.screen_background_color,
+            visible_points: Vec::new(),
+        }
+    }
+
+    pub fn get_data(&self) -> &RingBuffer<f32> {
+        &self.data
+    }
+
+    pub fn get_bounding_box(&self) -> Rectangle {
+        Rectangle::new(self.pos, self.size)
+    }
+
+    fn get_plot_area(&self) -> PlotArea {
+        PlotArea {
+            area: self.get_bounding_box(),
+            min: self.range.0,
+            max: self.range.1,
+        }
+    }
+
+    pub fn draw<D>(&mut self, display: &mut D) -> Result<(), D::Error>
+        where D: DrawTarget<Color=Rgb565> {
+        self.range = self.scale.get_range(self.data.iter());
+        display.fill_solid(&self.get_bounding_box(), self.background_color)?;
+        self.visible_points.clear();
+        self.draw_plot(display)
+    }
+
+    pub fn push<D>(&mut self, display: &mut D, value: f32) -> Result<(), D::Error>
+        where D: DrawTarget<Color=Rgb565> {
+        self.data.push(value);
+        if self.scale.get_range(self.data.iter()) != self.range {
+            return self.draw(display);
+        }
+        self.draw_plot(display)
+    }
+
+    fn draw_plot<D>(&mut self, display: &mut D) -> Result<(), D::Error>
+        where D: DrawTarget<Color=Rgb565> {
+        if self.visible_points.len() > 1 {
+            Polyline::new(&self.visible_points)
+                .into_styled(PrimitiveStyle::with_stroke(self.background_color, 1))
+                .draw(display)?;
+        }
+        self.visible_points = self.get_plot_area().get_points(&self.data);
+        if self.visible_points.len() > 1 {
+            Polyline::new(&self.visible_points)
+                .into_styled(PrimitiveStyle::with_stroke(self.line_color, 1))
+                .draw(display)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use profont::PROFONT_12_POINT;
+
+    use crate::memory_display::MemoryDisplay;
+
+    use super::*;
+
+    #[test]
+    fn fixed_scale_with_empty_range_is_widened() {
+        assert_eq!(AxisScale::Fixed(5.0, 5.0).get_range(core::iter::empty()), (4.0, 6.0));
+        assert_eq!(AxisScale::Fixed(5.0, 1.0).get_range(core::iter::empty()), (4.0, 6.0));
+        assert_eq!(AxisScale::Fixed(0.0, 10.0).get_range(core::iter::empty()), (0.0, 10.0));
+    }
+
+    /// Pixels of color below the x axis, where only the bar labels are drawn
+    fn count_label_pixels(display: &MemoryDisplay, chart: &BarChart, color: Rgb565) -> usize {
+        let plot = chart.get_plot_area();
+        let bottom = plot.area.top_left.y + plot.area.size.height as i32;
+        let columns = plot.area.top_left.x..plot.area.top_left.x + plot.area.size.width as i32;
+        let rows = bottom + 1..chart.get_bounding_box().bottom_right().unwrap().y + 1;
+        rows.flat_map(|y| columns.clone().map(move |x| Point::new(x, y)))
+            .filter(|pos| display.get_pixel(*pos) == Some(color))
+            .count()
+    }
+
+    #[test]
+    fn bar_chart_with_narrow_bars_draws_without_labels() {
+        let mut display = MemoryDisplay::new(128, 64);
+        let style = MonoTextStyle::new(&PROFONT_12_POINT, Rgb565::new(31, 63, 31));
+        let theme = Theme::new_dark_theme();
+        let labels = ["Monday", "Tuesday", "Wednesday", "Thursday", "Friday", "Saturday", "Sunday"];
+        let mut chart = BarChart::new(&labels, Point::zero(), Size::new(128, 64), AxisScale::Fixed(3.0, 3.0),
+                                      style, &theme);
+        chart.draw(&mut display).unwrap();
+        chart.set_value(&mut display, 0, 3.5).unwrap();
+
+        assert_eq!(count_label_pixels(&display, &chart, theme.text_color_primary), 0);
+        assert!(display.count_pixels(theme.button_background_color) > 0);
+    }
+
+    #[test]
+    fn bar_chart_draws_labels_and_bars() {
+        let mut display = MemoryDisplay::new(240, 120);
+        let style = MonoTextStyle::new(&PROFONT_12_POINT, Rgb565::new(31, 63, 31));
+        let theme = Theme::new_dark_theme();
+        let mut chart = BarChart::new(&["Mo", "Tu", "We"], Point::zero(), Size::new(240, 120),
+                                      AxisScale::Fixed(0.0, 10.0), style, &theme);
+        chart.draw(&mut display).unwrap();
+        assert!(count_label_pixels(&display, &chart, theme.text_color_primary) > 0);
+
+        let zero_bars = display.count_pixels(theme.button_background_color);
+        chart.set_value(&mut display, 1, 5.0).unwrap();
+        assert!(display.count_pixels(theme.button_background_color) > zero_bars);
+        chart.set_value(&mut display, 1, 0.0).unwrap();
+        assert_eq!(display.count_pixels(theme.button_background_color), zero_bars);
+    }
+}
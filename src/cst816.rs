@@ -0,0 +1,374 @@
+use embedded_hal::delay::DelayNs;
+use embedded_hal::digital::OutputPin;
+use embedded_hal_async::i2c::I2c;
+
+use crate::ft6236_asynch::{EventType, WakeError};
+use crate::touch::TouchTracker;
+
+pub const CST816_DEFAULT_ADDR: u8 = 0x15;
+
+pub mod regs {
+    pub const GESTURE_ID: u8 = 0x01;
+    pub const FINGER_NUM: u8 = 0x02;
+    pub const XPOS_H: u8 = 0x03;
+    pub const SLEEP_MODE: u8 = 0xE5;
+    pub const CHIP_ID: u8 = 0xA7;
+    pub const PROJ_ID: u8 = 0xA8;
+    pub const FW_VERSION: u8 = 0xA9;
+    pub const MOTION_MASK: u8 = 0xEC;
+    pub const AUTO_SLEEP_TIME: u8 = 0xF9;
+    pub const IRQ_CTL: u8 = 0xFA;
+    pub const LONG_PRESS_TIME: u8 = 0xFC;
+    pub const DIS_AUTO_SLEEP: u8 = 0xFE;
+}
+
+/// GESTURE_ID..YPOS_L
+const TOUCH_DATA_LEN: usize = 6;
+
+const SLEEP_MODE_DEEP_SLEEP: u8 = 0x03;
+
+const MOTION_MASK_DOUBLE_CLICK: u8 = 0x01;
+const MOTION_MASK_CONTINUOUS_UP_DOWN: u8 = 0x02;
+const MOTION_MASK_CONTINUOUS_LEFT_RIGHT: u8 = 0x04;
+
+const IRQ_CTL_TOUCH: u8 = 0x40;
+const IRQ_CTL_CHANGE: u8 = 0x20;
+const IRQ_CTL_MOTION: u8 = 0x10;
+const IRQ_CTL_LONG_PRESS_ONCE: u8 = 0x01;
+
+const CHIPID_CST716: u8 = 0x20;
+const CHIPID_CST816S: u8 = 0xB4;
+const CHIPID_CST816T: u8 = 0xB5;
+const CHIPID_CST816D: u8 = 0xB6;
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error<E> {
+    I2c(E),
+    /// CHIP_ID register did not contain a known CST716/CST816 id
+    InvalidChipId(u8),
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ChipId {
+    CST716,
+    CST816S,
+    CST816T,
+    CST816D,
+}
+
+impl ChipId {
+    fn from_u8(chipid: u8) -> Option<Self> {
+        match chipid {
+            CHIPID_CST716 => Some(ChipId::CST716),
+            CHIPID_CST816S => Some(ChipId::CST816S),
+            CHIPID_CST816T => Some(ChipId::CST816T),
+            CHIPID_CST816D => Some(ChipId::CST816D),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ChipInfo {
+    pub chip_id: ChipId,
+    pub project_id: u8,
+    pub firmware_version: u8,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum Gesture {
+    SlideUp = 0x01,
+    SlideDown = 0x02,
+    SlideLeft = 0x03,
+    SlideRight = 0x04,
+    SingleClick = 0x05,
+    /// only reported with double_click enabled in GestureConfig
+    DoubleClick = 0x0B,
+    LongPress = 0x0C,
+}
+
+impl Gesture {
+    pub(crate) fn from_u8(gesture: u8) -> Option<Self> {
+        match gesture {
+            0x01 => Some(Gesture::SlideUp),
+            0x02 => Some(Gesture::SlideDown),
+            0x03 => Some(Gesture::SlideLeft),
+            0x04 => Some(Gesture::SlideRight),
+            0x05 => Some(Gesture::SingleClick),
+            0x0B => Some(Gesture::DoubleClick),
+            0x0C => Some(Gesture::LongPress),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PointEvent {
+    pub x: u16,
+    pub y: u16,
+    pub event: EventType,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TouchData {
+    pub gesture: Option<Gesture>,
+    /// Single touch controller, None if not touched
+    pub point: Option<PointEvent>,
+}
+
+impl TouchData {
+    /// decode GESTURE_ID..YPOS_L
+    fn from_registers(buf: &[u8; TOUCH_DATA_LEN]) -> Self {
+        let point = if buf[1] & 0x0F != 0 {
+            EventType::from_u8(buf[2] >> 6).map(|event| PointEvent {
+                x: (((buf[2] as u16) & 0x0F) << 8) | (buf[3] as u16),
+                y: (((buf[4] as u16) & 0x0F) << 8) | (buf[5] as u16),
+                event,
+            })
+        } else {
+            None
+        };
+        TouchData {
+            gesture: Gesture::from_u8(buf[0]),
+            point,
+        }
+    }
+}
+
+/// Built in gestures, in MOTION_MASK register
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub struct GestureConfig {
+    pub double_click: bool,
+    /// repeat slide up and down while the finger keeps moving
+    pub continuous_up_down: bool,
+    /// repeat slide left and right while the finger keeps moving
+    pub continuous_left_right: bool,
+}
+
+/// Events pulsing the INT line, in IRQ_CTL register
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub struct InterruptConfig {
+    /// periodic pulses while touched
+    pub touch: bool,
+    /// pulse when the touch state changes
+    pub change: bool,
+    /// pulse when a gesture is detected
+    pub motion: bool,
+    /// only one pulse on long press
+    pub long_press_once: bool,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub enum AutoSleep {
+    /// the chip stays active and answers on I2C all the time
+    Disabled,
+    /// seconds without touch before entering low power mode, the chip does not answer
+    /// on I2C while sleeping and wakes up on the next touch
+    AfterSeconds(u8),
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug, Default)]
+pub struct Config {
+    pub gesture: Option<GestureConfig>,
+    pub interrupt: Option<InterruptConfig>,
+    /// seconds before LongPress is reported, 0 disables long press, in LONG_PRESS_TIME register
+    pub long_press_time: Option<u8>,
+    /// in DIS_AUTO_SLEEP and AUTO_SLEEP_TIME registers
+    pub auto_sleep: Option<AutoSleep>,
+}
+
+impl Config {
+    /// Register writes for this configuration, None values are skipped
+    fn to_registers(self) -> [Option<(u8, u8)>; 5] {
+        let motion_mask = self.gesture.map(|g| {
+            let mut mask = 0;
+            if g.double_click {
+                mask |= MOTION_MASK_DOUBLE_CLICK;
+            }
+            if g.continuous_up_down {
+                mask |= MOTION_MASK_CONTINUOUS_UP_DOWN;
+            }
+            if g.continuous_left_right {
+                mask |= MOTION_MASK_CONTINUOUS_LEFT_RIGHT;
+            }
+            mask
+        });
+        let irq_ctl = self.interrupt.map(|i| {
+            let mut ctl = 0;
+            if i.touch {
+                ctl |= IRQ_CTL_TOUCH;
+            }
+            if i.change {
+                ctl |= IRQ_CTL_CHANGE;
+            }
+            if i.motion {
+                ctl |= IRQ_CTL_MOTION;
+            }
+            if i.long_press_once {
+                ctl |= IRQ_CTL_LONG_PRESS_ONCE;
+            }
+            ctl
+        });
+        let (dis_auto_sleep, auto_sleep_time) = match self.auto_sleep {
+            Some(AutoSleep::Disabled) => (Some(1), None),
+            Some(AutoSleep::AfterSeconds(seconds)) => (Some(0), Some(seconds)),
+            None => (None, None),
+        };
+        [
+            motion_mask.map(|v| (regs::MOTION_MASK, v)),
+            irq_ctl.map(|v| (regs::IRQ_CTL, v)),
+            self.long_press_time.map(|v| (regs::LONG_PRESS_TIME, v)),
+            dis_auto_sleep.map(|v| (regs::DIS_AUTO_SLEEP, v)),
+            auto_sleep_time.map(|v| (regs::AUTO_SLEEP_TIME, v)),
+        ]
+    }
+}
+
+pub struct CST816<I2C> {
+    i2c: I2C,
+    addr: u8,
+    config: Config,
+    pub(crate) tracker: TouchTracker,
+}
+
+impl<I2C> CST816<I2C>
+where
+    I2C: I2c,
+{
+    pub fn new(i2c: I2C) -> Self {
+        CST816::new_with_addr(i2c, CST816_DEFAULT_ADDR)
+    }
+
+    pub fn new_with_addr(i2c: I2C, addr: u8) -> Self {
+        CST816 {
+            i2c,
+            addr,
+            config: Config::default(),
+            tracker: TouchTracker::new(),
+        }
+    }
+
+    /// With auto sleep enabled the chip has to be touched or reset before it answers
+    pub async fn init(&mut self, config: Config) -> Result<ChipInfo, Error<I2C::Error>> {
+        let info = self.read_chip_info().await?;
+
+        #[cfg(feature = "defmt")]
+        defmt::info!("chip {}, project 0x{:02x}, firmware 0x{:02x}", info.chip_id, info.project_id, info.firmware_version);
+
+        self.write_config(config).await.map_err(Error::I2c)?;
+        Ok(info)
+    }
+
+    pub async fn read_chip_info(&mut self) -> Result<ChipInfo, Error<I2C::Error>> {
+        let mut buf = [0u8; 3];
+        self.i2c.write_read(self.addr, &[regs::CHIP_ID], &mut buf).await.map_err(Error::I2c)?;
+        let chip_id = match ChipId::from_u8(buf[0]) {
+            Some(chip_id) => chip_id,
+            None => {
+                #[cfg(feature = "defmt")]
+                defmt::error!("invalid chipid 0x{:02x}", buf[0]);
+                return Err(Error::InvalidChipId(buf[0]));
+            }
+        };
+        Ok(ChipInfo {
+            chip_id,
+            project_id: buf[1],
+            firmware_version: buf[2],
+        })
+    }
+
+    /// Write the configuration registers, None values keep the chip setting
+    pub async fn write_config(&mut self, config: Config) -> Result<(), I2C::Error> {
+        self.config = config;
+        for (reg_addr, value) in config.to_registers().into_iter().flatten() {
+            self.write_reg(reg_addr, value).await?;
+        }
+        Ok(())
+    }
+
+    pub fn reset<P: OutputPin, D: DelayNs>(
+        &mut self,
+        rst: &mut P,
+        delay: &mut D,
+    ) -> Result<(), P::Error> {
+        rst.set_low()?;
+        delay.delay_ms(10);
+        rst.set_high()?;
+        delay.delay_ms(50);
+        self.tracker = TouchTracker::new();
+
+        Ok(())
+    }
+
+    /// Reset and reapply the configuration, the reset restores the chip defaults
+    pub async fn reset_and_configure<P: OutputPin, D: DelayNs>(
+        &mut self,
+        rst: &mut P,
+        delay: &mut D,
+    ) -> Result<(), WakeError<I2C::Error, P::Error>> {
+        self.reset(rst, delay).map_err(WakeError::Pin)?;
+        self.write_config(self.config).await.map_err(WakeError::I2c)
+    }
+
+    /// Deep sleep, only a reset wakes the chip up again
+    pub async fn sleep(&mut self) -> Result<(), I2C::Error> {
+        self.write_reg(regs::SLEEP_MODE, SLEEP_MODE_DEEP_SLEEP).await
+    }
+
+    /// Read gesture and touch point in one transaction
+    pub async fn read_touch_data(&mut self) -> Result<TouchData, I2C::Error> {
+        let mut buf = [0u8; TOUCH_DATA_LEN];
+        self.i2c.write_read(self.addr, &[regs::GESTURE_ID], &mut buf).await?;
+        Ok(TouchData::from_registers(&buf))
+    }
+
+    pub async fn get_gesture(&mut self) -> Result<Option<Gesture>, I2C::Error> {
+        let mut buf = [0u8];
+        self.i2c.write_read(self.addr, &[regs::GESTURE_ID], &mut buf).await?;
+        Ok(Gesture::from_u8(buf[0]))
+    }
+
+    async fn write_reg(&mut self, reg_addr: u8, value: u8) -> Result<(), I2C::Error> {
+        self.i2c.write(self.addr, &[reg_addr, value]).await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_touch_point_and_gesture() {
+        let data = TouchData::from_registers(&[0x05, 0x01, 0x80 | 0x01, 0x23, 0x00, 0xF0]);
+        assert_eq!(data.gesture, Some(Gesture::SingleClick));
+        assert_eq!(data.point, Some(PointEvent { x: 0x123, y: 0xF0, event: EventType::Contact }));
+
+        let data = TouchData::from_registers(&[0x00, 0x01, 0x40, 0x10, 0x00, 0x20]);
+        assert_eq!(data.gesture, None);
+        assert_eq!(data.point, Some(PointEvent { x: 0x10, y: 0x20, event: EventType::LiftUp }));
+    }
+
+    #[test]
+    fn no_point_without_finger() {
+        let data = TouchData::from_registers(&[0x0C, 0x00, 0x80, 0x10, 0x00, 0x20]);
+        assert_eq!(data.gesture, Some(Gesture::LongPress));
+        assert_eq!(data.point, None);
+        assert_eq!(TouchData::from_registers(&[0x42, 0x01, 0xC0, 0x10, 0x00, 0x20]).point, None);
+    }
+
+    #[test]
+    fn known_chip_ids() {
+        assert_eq!(ChipId::from_u8(0xB5), Some(ChipId::CST816T));
+        assert_eq!(ChipId::from_u8(0x20), Some(ChipId::CST716));
+        assert_eq!(ChipId::from_u8(0x36), None);
+    }
+}
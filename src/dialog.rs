@@ -0,0 +1,424 @@
+extern crate alloc;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use embedded_graphics::draw_target::DrawTarget;
+use embedded_graphics::Drawable;
+use embedded_graphics::geometry::{Point, Size};
+use embedded_graphics::image::{Image, ImageDrawable};
+use embedded_graphics::mono_font::MonoTextStyle;
+use embedded_graphics::pixelcolor::Rgb565;
+use embedded_graphics::prelude::Primitive;
+use embedded_graphics::primitives::{PrimitiveStyle, PrimitiveStyleBuilder, Rectangle, RoundedRectangle, StrokeAlignment};
+use embedded_graphics::text::{Alignment, Baseline, Text, TextStyle, TextStyleBuilder};
+
+use crate::graphics::{GraphicUtils, Theme};
+
+pub const DIALOG_MAX_BUTTONS: usize = 3;
+
+const DIALOG_MARGIN: u32 = 8;
+const DIALOG_BORDER_WIDTH: u32 = 2;
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum DialogEvent {
+    /// touch was inside the modal area but did not hit a button
+    Consumed,
+    /// index of the button that was pressed
+    ButtonPressed(usize),
+}
+
+pub struct Dialog<'a, T> {
+    title: String,
+    message: String,
+    buttons: Vec<String>,
+    icon: Option<&'a T>,
+    pos: Point,
+    size: Size,
+    open: bool,
+    title_color: Rgb565,
+    background_color: Rgb565,
+    border_color: Rgb565,
+    button_background_color: Rgb565,
+    button_foreground_color: Rgb565,
+    screen_background_color: Rgb565,
+    character_style: MonoTextStyle<'a, Rgb565>,
+}
+
+impl<'a, T: ImageDrawable<Color=Rgb565>> Dialog<'a, T> {
+    pub fn new(title: &str, message: &str, buttons: &[&str], position: Point, size: Size,
+               character_style: MonoTextStyle<'a, Rgb565>, theme: &Theme) -> Self {
+        Dialog {
+            title: String::from(title),
+            message: String::from(message),
+            buttons: buttons.iter().take(DIALOG_MAX_BUTTONS).map(|b| String::from(*b)).collect(),
+            icon: None,
+            pos: position,
+            size,
+            open: false,
+            title_color: theme.highlight_color,
+            background_color: theme.screen_background_color,
+            border_color: theme.text_color_primary,
+            button_background_color: theme.button_background_color,
+            button_foreground_color: theme.button_foreground_color,
+            screen_background_color: theme.screen_background_color,
+            character_style,
+        }
+    }
+
+    pub fn new_error(title: &str, message: &str, buttons: &[&str], position: Point, size: Size,
+                     character_style: MonoTextStyle<'a, Rgb565>, theme: &Theme) -> Self {
+        let mut dialog = Dialog::new(title, message, buttons, position, size, character_style, theme);
+        dialog.title_color = theme.error_color;
+        dialog.border_color = theme.error_color;
+        dialog
+    }
+
+    pub fn set_icon(&mut self, icon: &'a T) {
+        self.icon = Some(icon);
+    }
+
+    pub fn set_message(&mut self, message: &str) {
+        self.message = String::from(message);
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    pub fn get_bounding_box(&self) -> Rectangle {
+        Rectangle::new(self.pos, self.size)
+    }
+
+    fn get_fill_style(&self, color: Rgb565) -> PrimitiveStyle<Rgb565> {
+        PrimitiveStyleBuilder::new()
+            .fill_color(color)
+            .build()
+    }
+
+    fn get_frame_style(&self) -> PrimitiveStyle<Rgb565> {
+        PrimitiveStyleBuilder::new()
+            .fill_color(self.background_color)
+            .stroke_color(self.border_color)
+            .stroke_width(DIALOG_BORDER_WIDTH)
+            .stroke_alignment(StrokeAlignment::Inside)
+            .build()
+    }
+
+    fn get_text_style(&self, alignment: Alignment) -> TextStyle {
+        TextStyleBuilder::new()
+            .alignment(alignment)
+            .baseline(Baseline::Top)
+            .build()
+    }
+
+    fn get_title_height(&self) -> u32 {
+        self.character_style.font.character_size.height + 2 * DIALOG_MARGIN
+    }
+
+    fn get_button_height(&self) -> u32 {
+        self.character_style.font.character_size.height + 2 * DIALOG_MARGIN
+    }
+
+    fn get_button_bounding_box(&self, index: usize) -> Rectangle {
+        let count = self.buttons.len().max(1) as u32;
+        let button_width = (self.size.width.saturating_sub(DIALOG_MARGIN * (count + 1)) / count).max(1);
+        let button_height = self.get_button_height();
+        let x = self.pos.x + (DIALOG_MARGIN + index as u32 * (button_width + DIALOG_MARGIN)) as i32;
+        let y = self.pos.y + self.size.height.saturating_sub(button_height + DIALOG_MARGIN) as i32;
+        Rectangle::new(Point::new(x, y), Size::new(button_width, button_height))
+    }
+
+    fn get_message_lines(&self, width: u32) -> Vec<String> {
+        let chars_per_line = (width / self.character_style.font.character_size.width).max(1) as usize;
+        let mut lines = Vec::new();
+        for paragraph in self.message.split('\n') {
+            let mut line = String::new();
+            for word in paragraph.split_whitespace() {
+                if !line.is_empty() && line.len() + 1 + word.len() > chars_per_line {
+                    lines.push(line);
+                    line = String::new();
+                }
+                if !line.is_empty() {
+                    line.push(' ');
+                }
+                line.push_str(word);
+            }
+            lines.push(line);
+        }
+        lines
+    }
+
+    pub fn open<D>(&mut self, display: &mut D) -> Result<(), D::Error>
+        where D: DrawTarget<Color=Rgb565> {
+        self.open = true;
+        self.draw(display)
+    }
+
+    /// Clears the dialog area with the screen background and returns the region
+    /// that must be redrawn by the screen underneath
+    pub fn close<D>(&mut self, display: &mut D) -> Result<Rectangle, D::Error>
+        where D: DrawTarget<Color=Rgb565> {
+        self.open = false;
+        let bounding_box = self.get_bounding_box();
+        bounding_box
+            .into_styled(self.get_fill_style(self.screen_background_color))
+            .draw(display)?;
+        Ok(bounding_box)
+    }
+
+    pub fn draw<D>(&self, display: &mut D) -> Result<(), D::Error>
+        where D: DrawTarget<Color=Rgb565> {
+        self.get_bounding_box()
+            .into_styled(self.get_frame_style())
+            .draw(display)?;
+
+        // title bar
+        let title_height = self.get_title_height();
+        Rectangle::new(self.pos, Size::new(self.size.width, title_height))
+            .into_styled(self.get_fill_style(self.title_color))
+            .draw(display)?;
+        let mut title_character_style = self.character_style;
+        title_character_style.text_color = Some(self.button_foreground_color);
+        let title_width = self.size.width.saturating_sub(2 * DIALOG_MARGIN);
+        if let Some(title) = GraphicUtils::get_text_with_ellipsis_checked(title_width, self.title.as_str(), self.character_style.font) {
+            GraphicUtils::display_text(display, Point::new(self.pos.x + DIALOG_MARGIN as i32, self.pos.y + DIALOG_MARGIN as i32),
+                                       title_character_style, self.get_text_style(Alignment::Left), title.as_str())?;
+        }
+
+        // icon and message
+        let content_top = self.pos.y + (title_height + DIALOG_MARGIN) as i32;
+        let mut message_x = self.pos.x + DIALOG_MARGIN as i32;
+        if let Some(icon) = self.icon {
+            Image::new(icon, Point::new(message_x, content_top)).draw(display)?;
+            message_x += (icon.size().width + DIALOG_MARGIN) as i32;
+        }
+        let message_width = (self.pos.x + self.size.width as i32 - DIALOG_MARGIN as i32 - message_x).max(0) as u32;
+        let line_height = self.character_style.font.character_size.height as i32;
+        let content_bottom = self.get_button_bounding_box(0).top_left.y - DIALOG_MARGIN as i32;
+        for (index, line) in self.get_message_lines(message_width).iter().enumerate() {
+            let line_y = content_top + index as i32 * line_height;
+            if line_y + line_height > content_bottom {
+                break;
+            }
+            if let Some(visible_line) = GraphicUtils::get_text_with_ellipsis_checked(message_width, line.as_str(), self.character_style.font) {
+                GraphicUtils::display_text(display, Point::new(message_x, line_y), self.character_style,
+                                           self.get_text_style(Alignment::Left), visible_line.as_str())?;
+            }
+        }
+
+        // buttons
+        let mut button_character_style = self.character_style;
+        button_character_style.text_color = Some(self.button_foreground_color);
+        for (index, button) in self.buttons.iter().enumerate() {
+            let bounding_box = self.get_button_bounding_box(index);
+            RoundedRectangle::with_equal_corners(bounding_box, Size::new(10, 10))
+                .into_styled(self.get_fill_style(self.button_background_color))
+                .draw(display)?;
+            if let Some(text) = GraphicUtils::get_text_with_ellipsis_checked(bounding_box.size.width, button.as_str(), self.character_style.font) {
+                Text::with_text_style(
+                    text.as_str(),
+                    Point::new(bounding_box.center().x, bounding_box.top_left.y + DIALOG_MARGIN as i32),
+                    button_character_style,
+                    self.get_text_style(Alignment::Center),
+                )
+                    .draw(display)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// While the dialog is open every touch is consumed, touches outside of
+    /// the dialog must not reach the screen underneath
+    pub fn handle_touch(&self, pos: Point) -> Option<DialogEvent> {
+        if !self.open {
+            return None;
+        }
+        for index in 0..self.buttons.len() {
+            if self.get_button_bounding_box(index).contains(pos) {
+                return Some(DialogEvent::ButtonPressed(index));
+            }
+        }
+        Some(DialogEvent::Consumed)
+    }
+}
+
+pub struct Toast<'a> {
+    text: String,
+    pos: Point,
+    size: Size,
+    duration_ms: u64,
+    shown_at_ms: Option<u64>,
+    background_color: Rgb565,
+    screen_background_color: Rgb565,
+    character_style: MonoTextStyle<'a, Rgb565>,
+}
+
+impl<'a> Toast<'a> {
+    pub fn new(position: Point, size: Size, duration_ms: u64,
+               character_style: MonoTextStyle<'a, Rgb565>, theme: &Theme) -> Self {
+        let mut toast_character_style = character_style;
+        toast_character_style.text_color = Some(theme.button_foreground_color);
+        Toast {
+            text: String::new(),
+            pos: position,
+            size,
+            duration_ms,
+            shown_at_ms: None,
+            background_color: theme.button_background_color,
+            screen_background_color: theme.screen_background_color,
+            character_style: toast_character_style,
+        }
+    }
+
+    pub fn new_error(position: Point, size: Size, duration_ms: u64,
+                     character_style: MonoTextStyle<'a, Rgb565>, theme: &Theme) -> Self {
+        let mut toast = Toast::new(position, size, duration_ms, character_style, theme);
+        toast.background_color = theme.error_color;
+        toast
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.shown_at_ms.is_some()
+    }
+
+    pub fn get_bounding_box(&self) -> Rectangle {
+        Rectangle::new(self.pos, self.size)
+    }
+
+    fn get_fill_style(&self, color: Rgb565) -> PrimitiveStyle<Rgb565> {
+        PrimitiveStyleBuilder::new()
+            .fill_color(color)
+            .build()
+    }
+
+    fn get_text_style(&self) -> TextStyle {
+        TextStyleBuilder::new()
+            .alignment(Alignment::Center)
+            .baseline(Baseline::Middle)
+            .build()
+    }
+
+    pub fn show<D>(&mut self, display: &mut D, text: &str, now_ms: u64) -> Result<(), D::Error>
+        where D: DrawTarget<Color=Rgb565> {
+        self.text = String::from(text);
+        self.shown_at_ms = Some(now_ms);
+        self.draw(display)
+    }
+
+    pub fn draw<D>(&self, display: &mut D) -> Result<(), D::Error>
+        where D: DrawTarget<Color=Rgb565> {
+        RoundedRectangle::with_equal_corners(self.get_bounding_box(), Size::new(10, 10))
+            .into_styled(self.get_fill_style(self.background_color))
+            .draw(display)?;
+        let text_width = self.size.width.saturating_sub(2 * DIALOG_MARGIN);
+        if let Some(text) = GraphicUtils::get_text_with_ellipsis_checked(text_width, self.text.as_str(), self.character_style.font) {
+            GraphicUtils::display_text(display, self.get_bounding_box().center(), self.character_style,
+                                       self.get_text_style(), text.as_str())?;
+        }
+        Ok(())
+    }
+
+    /// Hides the toast once its timeout expired, returns the region that must be
+    /// redrawn by the screen underneath
+    pub fn update<D>(&mut self, display: &mut D, now_ms: u64) -> Result<Option<Rectangle>, D::Error>
+        where D: DrawTarget<Color=Rgb565> {
+        if let Some(shown_at_ms) = self.shown_at_ms {
+            if now_ms.saturating_sub(shown_at_ms) >= self.duration_ms {
+                return self.hide(display).map(Some);
+            }
+        }
+        Ok(None)
+    }
+
+    pub fn hide<D>(&mut self, display: &mut D) -> Result<Rectangle, D::Error>
+        where D: DrawTarget<Color=Rgb565> {
+        self.shown_at_ms = None;
+        let bounding_box = self.get_bounding_box();
+        bounding_box
+            .into_styled(self.get_fill_style(self.screen_background_color))
+            .draw(display)?;
+        Ok(bounding_box)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embedded_graphics::image::ImageRaw;
+    use embedded_graphics::primitives::PointsIter;
+    use profont::PROFONT_12_POINT;
+
+    use crate::memory_display::MemoryDisplay;
+
+    use super::*;
+
+    fn count_pixels_in(display: &MemoryDisplay, area: Rectangle, color: Rgb565) -> usize {
+        area.points().filter(|pos| display.get_pixel(*pos) == Some(color)).count()
+    }
+
+    #[test]
+    fn dialog_draws_title_message_and_button_labels() {
+        let mut display = MemoryDisplay::new(240, 160);
+        let message_color = Rgb565::new(31, 0, 31);
+        let style = MonoTextStyle::new(&PROFONT_12_POINT, message_color);
+        let theme = Theme::new_dark_theme();
+        let mut dialog: Dialog<ImageRaw<Rgb565>> = Dialog::new("Delete", "Remove the file?", &["OK", "Cancel"],
+                                                               Point::new(10, 10), Size::new(220, 140), style, &theme);
+        dialog.open(&mut display).unwrap();
+
+        let title_bar = Rectangle::new(Point::new(10, 10), Size::new(220, dialog.get_title_height()));
+        assert!(count_pixels_in(&display, title_bar, theme.highlight_color) > 0);
+        assert!(count_pixels_in(&display, title_bar, theme.button_foreground_color) > 0);
+        assert!(display.count_pixels(message_color) > 0);
+        for index in 0..2 {
+            let button = dialog.get_button_bounding_box(index);
+            assert!(count_pixels_in(&display, button, theme.button_background_color) > 0);
+            assert!(count_pixels_in(&display, button, theme.button_foreground_color) > 0);
+        }
+
+        let cancel = dialog.get_button_bounding_box(1).center();
+        assert_eq!(dialog.handle_touch(cancel), Some(DialogEvent::ButtonPressed(1)));
+        assert_eq!(dialog.handle_touch(Point::new(0, 0)), Some(DialogEvent::Consumed));
+        dialog.close(&mut display).unwrap();
+        assert_eq!(display.count_pixels(theme.screen_background_color), 240 * 160);
+        assert_eq!(dialog.handle_touch(cancel), None);
+    }
+
+    #[test]
+    fn toast_draws_the_text_until_it_expires() {
+        let mut display = MemoryDisplay::new(160, 64);
+        let style = MonoTextStyle::new(&PROFONT_12_POINT, Rgb565::new(31, 63, 31));
+        let theme = Theme::new_dark_theme();
+        let mut toast = Toast::new(Point::new(10, 10), Size::new(120, 30), 1000, style, &theme);
+        toast.show(&mut display, "Saved", 0).unwrap();
+        assert!(display.count_pixels(theme.button_foreground_color) > 0);
+        assert!(display.count_pixels(theme.button_background_color) > 0);
+
+        assert_eq!(toast.update(&mut display, 999).unwrap(), None);
+        assert_eq!(toast.update(&mut display, 1000).unwrap(), Some(toast.get_bounding_box()));
+        assert!(!toast.is_visible());
+        assert_eq!(display.count_pixels(theme.button_foreground_color), 0);
+    }
+
+    #[test]
+    fn small_dialog_with_many_buttons_draws_without_labels() {
+        let mut display = MemoryDisplay::new(64, 64);
+        let style = MonoTextStyle::new(&PROFONT_12_POINT, Rgb565::new(31, 63, 31));
+        let theme = Theme::new_dark_theme();
+        let mut dialog: Dialog<ImageRaw<Rgb565>> = Dialog::new("Title", "Message", &["Yes", "No", "Cancel"],
+                                                               Point::new(2, 2), Size::new(20, 12), style, &theme);
+        dialog.open(&mut display).unwrap();
+        assert_eq!(dialog.get_button_bounding_box(2).size.width, 1);
+        assert!(dialog.handle_touch(Point::new(3, 3)).is_some());
+    }
+
+    #[test]
+    fn narrow_toast_skips_the_text() {
+        let mut display = MemoryDisplay::new(64, 64);
+        let style = MonoTextStyle::new(&PROFONT_12_POINT, Rgb565::new(31, 63, 31));
+        let theme = Theme::new_dark_theme();
+        let mut toast = Toast::new(Point::zero(), Size::new(10, 10), 1000, style, &theme);
+        toast.show(&mut display, "Saved", 0).unwrap();
+        assert_eq!(display.count_pixels(toast.character_style.text_color.unwrap()), 0);
+    }
+}
@@ -3,8 +3,11 @@
 
 use embedded_hal::delay::DelayNs;
 use embedded_hal::digital::OutputPin;
+use embedded_hal_async::digital::Wait;
 use embedded_hal_async::i2c::I2c;
 
+use crate::touch::TouchTracker;
+
 pub const FT6236_DEFAULT_ADDR: u8 = 0x38;
 
 pub mod regs {
@@ -14,12 +17,136 @@ pub mod regs {
     pub const THRESHHOLD: u8 = 0x80;
     pub const NUMTOUCHES: u8 = 0x02;
     pub const GEST_ID: u8 = 0x01;
+    pub const DEV_MODE: u8 = 0x00;
+    pub const FILTER_COEFF: u8 = 0x85;
+    pub const CTRL: u8 = 0x86;
+    pub const TIME_ENTER_MONITOR: u8 = 0x87;
+    pub const PERIOD_ACTIVE: u8 = 0x88;
+    pub const PERIOD_MONITOR: u8 = 0x89;
+    pub const RADIAN_VALUE: u8 = 0x91;
+    pub const OFFSET_LEFT_RIGHT: u8 = 0x92;
+    pub const OFFSET_UP_DOWN: u8 = 0x93;
+    pub const DISTANCE_LEFT_RIGHT: u8 = 0x94;
+    pub const DISTANCE_UP_DOWN: u8 = 0x95;
+    pub const DISTANCE_ZOOM: u8 = 0x96;
+    pub const LIB_VER_H: u8 = 0xA1;
+    pub const LIB_VER_L: u8 = 0xA2;
+    pub const G_MODE: u8 = 0xA4;
+    pub const PWR_MODE: u8 = 0xA5;
 }
 
+/// DEV_MODE up to the second touch point, P1_XH..P1_MISC and P2_XH..P2_MISC
+pub(crate) const TOUCH_DATA_LEN: usize = 15;
+const TOUCH_POINT_LEN: usize = 6;
+
 const CHIPID_FT6206: u8 = 0x06;
 const CHIPID_FT6236: u8 = 0x36;
 const CHIPID_FT6236U: u8 = 0x64;
 
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error<E> {
+    I2c(E),
+    /// CHIPID register did not contain a known FT6x06/FT6x36 id
+    InvalidChipId(u8),
+}
+
+/// Error of operations using the bus and a pin, the wake functions and TouchEventStream
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum WakeError<I2cError, PinError> {
+    I2c(I2cError),
+    Pin(PinError),
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ChipId {
+    FT6206,
+    FT6236,
+    FT6236U,
+}
+
+impl ChipId {
+    pub(crate) fn from_u8(chipid: u8) -> Option<Self> {
+        match chipid {
+            CHIPID_FT6206 => Some(ChipId::FT6206),
+            CHIPID_FT6236 => Some(ChipId::FT6236),
+            CHIPID_FT6236U => Some(ChipId::FT6236U),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ChipInfo {
+    pub chip_id: ChipId,
+    pub vendor_id: u8,
+    pub firmware_version: u8,
+    pub library_version: u16,
+}
+
+/// Registers read into a ChipInfo, in the order of ChipInfo::from_registers
+pub(crate) const CHIP_INFO_REGS: [u8; 5] = [
+    regs::CHIPID,
+    regs::VENDID,
+    regs::FIRMVERS,
+    regs::LIB_VER_H,
+    regs::LIB_VER_L,
+];
+
+impl ChipInfo {
+    /// values read from CHIP_INFO_REGS, returns the chip id if it is unknown
+    pub(crate) fn from_registers(values: &[u8; CHIP_INFO_REGS.len()]) -> Result<Self, u8> {
+        Ok(ChipInfo {
+            chip_id: ChipId::from_u8(values[0]).ok_or(values[0])?,
+            vendor_id: values[1],
+            firmware_version: values[2],
+            library_version: u16::from_be_bytes([values[3], values[4]]),
+        })
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum InterruptMode {
+    /// INT is held low while the panel is touched
+    Polling = 0x00,
+    /// INT is pulsed on every new report
+    Trigger = 0x01,
+}
+
+impl InterruptMode {
+    pub(crate) fn from_u8(mode: u8) -> Self {
+        match mode {
+            0x01 => InterruptMode::Trigger,
+            _ => InterruptMode::Polling,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum PowerMode {
+    Active = 0x00,
+    Monitor = 0x01,
+    Hibernate = 0x03,
+}
+
+impl PowerMode {
+    pub(crate) fn from_u8(mode: u8) -> Option<Self> {
+        match mode {
+            0x00 => Some(PowerMode::Active),
+            0x01 => Some(PowerMode::Monitor),
+            0x03 => Some(PowerMode::Hibernate),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[repr(u8)]
@@ -44,7 +171,7 @@ impl Gesture {
         }
     }
 
-    fn from_u8(gesture: u8) -> Option<Self> {
+    pub(crate) fn from_u8(gesture: u8) -> Option<Self> {
         match gesture {
             0x10 => Some(Gesture::MoveUp),
             0x14 => Some(Gesture::MoveRight),
@@ -67,7 +194,7 @@ pub enum EventType {
 }
 
 impl EventType {
-    fn from_u8(event: u8) -> Option<Self> {
+    pub(crate) fn from_u8(event: u8) -> Option<Self> {
         match event {
             0b00 => Some(EventType::PressDown),
             0b01 => Some(EventType::LiftUp),
@@ -94,149 +221,637 @@ pub struct PointEvent {
     pub touch_id: u8,
 }
 
-pub struct FT6236<I2C> {
-    i2c: I2C,
-    addr: u8,
-}
+impl PointEvent {
+    /// decode Px_XH..Px_MISC
+    fn from_registers(buf: &[u8]) -> Option<Self> {
+        let event = EventType::from_u8(buf[0] >> 6)?;
 
-impl<I2C> FT6236<I2C>
-where
-    I2C: I2c,
-{
-    pub fn new(i2c: I2C) -> Self {
-        FT6236 {
-            i2c,
-            addr: FT6236_DEFAULT_ADDR,
+        let x = (((buf[0] as u16) & 0b111) << 8) | (buf[1] as u16);
+
+        let touch_id = buf[2] >> 4;
+        if touch_id == 0x0f {
+            return None; // invalid touch id
         }
-    }
+        let y = (((buf[2] as u16) & 0b111) << 8) | (buf[3] as u16);
+        let weight = buf[4];
+        let area = buf[5] & 0b1111;
 
-    pub fn new_with_addr(i2c: I2C, addr: u8) -> Self {
-        FT6236 { i2c, addr }
+        Some(PointEvent {
+            x,
+            y,
+            event,
+            weight,
+            area,
+            touch_id,
+        })
     }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TouchData {
+    pub gesture: Option<Gesture>,
+    /// Number of touches, 0, 1 or 2
+    pub number_of_touches: u8,
+    /// Only the first number_of_touches points are set
+    pub points: [Option<PointEvent>; 2],
+}
 
-    pub async fn init(&mut self, config: Config) -> Result<(), I2C::Error> {
-        let chipid = self.read_reg(regs::CHIPID).await?;
-        if chipid != CHIPID_FT6206 && chipid != CHIPID_FT6236 && chipid != CHIPID_FT6236U {
-            #[cfg(feature = "defmt")]
-            defmt::error!("invalid chipid 0x{:02x}", chipid);
+impl TouchData {
+    /// decode DEV_MODE..P2_MISC
+    pub(crate) fn from_registers(buf: &[u8; TOUCH_DATA_LEN]) -> Self {
+        let mut number_of_touches = buf[regs::NUMTOUCHES as usize] & 0b1111;
+        if number_of_touches > 2 {
+            number_of_touches = 0; // invalid
         }
+        let mut points = [None, None];
+        for (nth, point) in points.iter_mut().enumerate().take(number_of_touches as usize) {
+            let start = 0x03 + TOUCH_POINT_LEN * nth;
+            *point = PointEvent::from_registers(&buf[start..start + TOUCH_POINT_LEN]);
+        }
+        TouchData {
+            gesture: Gesture::from_u8(buf[regs::GEST_ID as usize]),
+            number_of_touches,
+            points,
+        }
+    }
+}
 
-        #[cfg(feature = "defmt")]
-        {
-            let vendid = self.read_reg(regs::VENDID)?;
-            let active_report_rate = self.read_reg(0x88)?;
-            let monitor_report_rate = self.read_reg(0x89)?;
-            let g_mode = self.read_reg(0xa4)?;
+pub struct FT6236<I2C> {
+    i2c: I2C,
+    addr: u8,
+    config: Config,
+    power_mode: PowerMode,
+    pub(crate) tracker: TouchTracker,
+}
 
-            defmt::info!("chipid 0x{:02x}, vendid 0x{:02x}", chipid, vendid);
-            defmt::info!("active report rate: 0x{:02x}", active_report_rate);
-            defmt::info!("monitor report rate: 0x{:02x}", monitor_report_rate);
-            defmt::info!("interrupt mode: 0x{:02x}", g_mode);
+/// Methods of FT6236, shared by the async and the blocking driver. Invoked with
+/// `async; await` here and without arguments in ft6236_blocking
+macro_rules! ft6236_driver {
+    ($($async:ident)?; $($await:ident)?) => {
+        impl<I2C> FT6236<I2C>
+        where
+            I2C: I2c,
+        {
+            pub fn new(i2c: I2C) -> Self {
+                FT6236::new_with_addr(i2c, FT6236_DEFAULT_ADDR)
+            }
+
+            pub fn new_with_addr(i2c: I2C, addr: u8) -> Self {
+                FT6236 {
+                    i2c,
+                    addr,
+                    config: Config::default(),
+                    power_mode: PowerMode::Active,
+                    tracker: TouchTracker::new(),
+                }
+            }
+
+            pub $($async)? fn init(&mut self, config: Config) -> Result<(), Error<I2C::Error>> {
+                let chipid = self.read_reg(regs::CHIPID)$(.$await)?.map_err(Error::I2c)?;
+                if ChipId::from_u8(chipid).is_none() {
+                    #[cfg(feature = "defmt")]
+                    defmt::error!("invalid chipid 0x{:02x}", chipid);
+                    return Err(Error::InvalidChipId(chipid));
+                }
+
+                #[cfg(feature = "defmt")]
+                {
+                    let vendid = self.read_reg(regs::VENDID)$(.$await)?.map_err(Error::I2c)?;
+                    let active_report_rate = self.read_reg(regs::PERIOD_ACTIVE)$(.$await)?.map_err(Error::I2c)?;
+                    let monitor_report_rate = self.read_reg(regs::PERIOD_MONITOR)$(.$await)?.map_err(Error::I2c)?;
+                    let g_mode = self.read_reg(regs::G_MODE)$(.$await)?.map_err(Error::I2c)?;
+
+                    defmt::info!("chipid 0x{:02x}, vendid 0x{:02x}", chipid, vendid);
+                    defmt::info!("active report rate: 0x{:02x}", active_report_rate);
+                    defmt::info!("monitor report rate: 0x{:02x}", monitor_report_rate);
+                    defmt::info!("interrupt mode: 0x{:02x}", g_mode);
+                }
+
+                self.write_config(config)$(.$await)?.map_err(Error::I2c)
+            }
+
+            /// Write the configuration registers, None values keep the chip setting
+            pub $($async)? fn write_config(&mut self, config: Config) -> Result<(), I2C::Error> {
+                self.config = config;
+                for (reg_addr, value) in config.to_registers().into_iter().flatten() {
+                    self.write_reg(reg_addr, value)$(.$await)??;
+                }
+                Ok(())
+            }
+
+            /// Read back the configuration registers, all values are set
+            pub $($async)? fn read_config(&mut self) -> Result<Config, I2C::Error> {
+                let mut values = [0u8; CONFIG_REGS.len()];
+                let mut buf = [0u8; MAX_CONFIG_BURST_LEN];
+                for (start_reg, len) in CONFIG_BURSTS {
+                    self.i2c.write_read(self.addr, &[start_reg], &mut buf[..len])$(.$await)??;
+                    Config::store_burst(&mut values, start_reg, &buf[..len]);
+                }
+                Ok(Config::from_registers(&values))
+            }
+
+            pub $($async)? fn read_chip_info(&mut self) -> Result<ChipInfo, Error<I2C::Error>> {
+                let mut values = [0u8; CHIP_INFO_REGS.len()];
+                for (value, reg_addr) in values.iter_mut().zip(CHIP_INFO_REGS) {
+                    *value = self.read_reg(reg_addr)$(.$await)?.map_err(Error::I2c)?;
+                }
+                ChipInfo::from_registers(&values).map_err(Error::InvalidChipId)
+            }
+
+            /// None if the register contains an undocumented value
+            pub $($async)? fn get_power_mode(&mut self) -> Result<Option<PowerMode>, I2C::Error> {
+                let mode = self.read_reg(regs::PWR_MODE)$(.$await)??;
+                Ok(PowerMode::from_u8(mode))
+            }
+
+            pub fn reset<P: OutputPin, D: DelayNs>(
+                &mut self,
+                rst: &mut P,
+                delay: &mut D,
+            ) -> Result<(), P::Error> {
+                rst.set_high()?;
+                delay.delay_ms(5);
+                rst.set_low()?;
+                delay.delay_ms(10); // min = 5ms
+                rst.set_high()?;
+                delay.delay_ms(350); // min = 300ms
+                self.power_mode = PowerMode::Active;
+                self.tracker = TouchTracker::new();
+
+                Ok(())
+            }
+
+            /// Last power mode set through the driver, the chip can not be queried while hibernating.
+            /// With auto_monitor_mode the chip switches between active and monitor on its own,
+            /// use get_power_mode to read the register then
+            pub fn power_mode(&self) -> PowerMode {
+                self.power_mode
+            }
+
+            /// Switch between active and monitor mode or enter hibernate. Use one of the
+            /// wake functions to leave hibernate, the chip does not answer on I2C until then
+            pub $($async)? fn set_power_mode(&mut self, mode: PowerMode) -> Result<(), I2C::Error> {
+                self.write_reg(regs::PWR_MODE, mode as u8)$(.$await)??;
+                self.power_mode = mode;
+                Ok(())
+            }
+
+            pub $($async)? fn hibernate(&mut self) -> Result<(), I2C::Error> {
+                self.set_power_mode(PowerMode::Hibernate)$(.$await)?
+            }
+
+            /// Wake from hibernate with a reset pulse, the configuration is restored afterwards
+            pub $($async)? fn wake_with_reset<P: OutputPin, D: DelayNs>(
+                &mut self,
+                rst: &mut P,
+                delay: &mut D,
+            ) -> Result<(), WakeError<I2C::Error, P::Error>> {
+                self.reset(rst, delay).map_err(WakeError::Pin)?;
+                self.write_config(self.config)$(.$await)?.map_err(WakeError::I2c)
+            }
+
+            /// Wake from hibernate by pulling INT low, int must be the INT line
+            /// configured as output and switched back to input by the caller afterwards
+            pub $($async)? fn wake_with_int<P: OutputPin, D: DelayNs>(
+                &mut self,
+                int: &mut P,
+                delay: &mut D,
+            ) -> Result<(), WakeError<I2C::Error, P::Error>> {
+                int.set_low().map_err(WakeError::Pin)?;
+                delay.delay_ms(1); // 0.5ms - 1ms
+                int.set_high().map_err(WakeError::Pin)?;
+                delay.delay_ms(10);
+                self.power_mode = PowerMode::Active;
+                self.tracker = TouchTracker::new();
+                self.write_config(self.config)$(.$await)?.map_err(WakeError::I2c)
+            }
+
+            /// Number of touches, 0, 1 or 2
+            pub $($async)? fn get_number_of_touches(&mut self) -> Result<u8, I2C::Error> {
+                let n = self.read_reg(regs::NUMTOUCHES)$(.$await)?? & 0b1111;
+                if n <= 2 {
+                    Ok(n)
+                } else {
+                    Ok(0) // invalid
+                }
+            }
+
+            /// get first touch point, use get_points to read both points with one transaction
+            pub $($async)? fn get_point0(&mut self) -> Result<Option<PointEvent>, I2C::Error> {
+                self.get_point(0)$(.$await)?
+            }
+
+            /// get second touch point
+            pub $($async)? fn get_point1(&mut self) -> Result<Option<PointEvent>, I2C::Error> {
+                self.get_point(1)$(.$await)?
+            }
+
+            #[inline]
+            pub $($async)? fn get_point(&mut self, nth: u8) -> Result<Option<PointEvent>, I2C::Error> {
+                if nth > 1 {
+                    return Ok(None);
+                }
+                let data = self.read_touch_data()$(.$await)??;
+                Ok(data.points[nth as usize])
+            }
+
+            /// Both touch points decoded from one read_touch_data transaction
+            pub $($async)? fn get_points(&mut self) -> Result<[Option<PointEvent>; 2], I2C::Error> {
+                let data = self.read_touch_data()$(.$await)??;
+                Ok(data.points)
+            }
+
+            /// Read gesture, number of touches and both touch points in one transaction
+            pub $($async)? fn read_touch_data(&mut self) -> Result<TouchData, I2C::Error> {
+                let mut buf = [0u8; TOUCH_DATA_LEN];
+                self.i2c
+                    .write_read(self.addr, &[regs::DEV_MODE], &mut buf)$(.$await)??;
+                Ok(TouchData::from_registers(&buf))
+            }
+
+            /// Get the gesture, this is not available for some touch panels
+            pub $($async)? fn get_gesture(&mut self) -> Result<Option<Gesture>, I2C::Error> {
+                let gesture = self.read_reg(regs::GEST_ID)$(.$await)??;
+                Ok(Gesture::from_u8(gesture))
+            }
+
+            $($async)? fn read_reg(&mut self, reg_addr: u8) -> Result<u8, I2C::Error> {
+                let mut buf = [0u8];
+                self.i2c.write_read(self.addr, &[reg_addr], &mut buf)$(.$await)??;
+
+                Ok(buf[0])
+            }
+
+            $($async)? fn write_reg(&mut self, reg_addr: u8, value: u8) -> Result<(), I2C::Error> {
+                self.i2c.write(self.addr, &[reg_addr, value])$(.$await)??;
+
+                Ok(())
+            }
         }
+    };
+}
 
-        self.write_reg(regs::THRESHHOLD, config.threshhold).await?;
+pub(crate) use ft6236_driver;
 
-        Ok(())
-    }
+ft6236_driver!(async; await);
 
-    pub fn reset<P: OutputPin, D: DelayNs>(
-        &mut self,
-        rst: &mut P,
-        delay: &mut D,
-    ) -> Result<(), P::Error> {
-        rst.set_high()?;
-        delay.delay_ms(5);
-        rst.set_low()?;
-        delay.delay_ms(10); // min = 5ms
-        rst.set_high()?;
-        delay.delay_ms(350); // min = 300ms
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub struct Config {
+    /// threshold for touch detection
+    pub threshhold: u8,
+    /// filter function coefficient, in ID_G_THDIFF register
+    pub filter_coefficient: Option<u8>,
+    /// report rate in active mode, in ID_G_PERIODACTIVE register
+    pub active_report_rate: Option<u8>,
+    /// report rate in monitor mode, in ID_G_PERIODMONITOR register
+    pub monitor_report_rate: Option<u8>,
+    /// in ID_G_MODE register
+    pub interrupt_mode: Option<InterruptMode>,
+    /// switch to monitor mode when there is no touch, in ID_G_CTRL register
+    pub auto_monitor_mode: Option<bool>,
+    /// seconds without touch before switching to monitor mode, in ID_G_TIMEENTERMONITOR register
+    pub monitor_mode_delay: Option<u8>,
+    pub gesture: Option<GestureParams>,
+}
 
-        Ok(())
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            threshhold: 0x40,
+            filter_coefficient: None,
+            active_report_rate: None,
+            monitor_report_rate: None,
+            interrupt_mode: None,
+            auto_monitor_mode: None,
+            monitor_mode_delay: None,
+            gesture: None,
+        }
     }
+}
 
-    /// Number of touches, 0, 1 or 2
-    pub async fn get_number_of_touches(&mut self) -> Result<u8, I2C::Error> {
-        let n = self.read_reg(regs::NUMTOUCHES).await?;
-        if n & 0b11 <= 2 {
-            Ok(n)
-        } else {
-            Ok(0) // invalid
+/// Registers read back into a Config, in the order of Config::from_registers
+pub(crate) const CONFIG_REGS: [u8; 13] = [
+    regs::THRESHHOLD,
+    regs::FILTER_COEFF,
+    regs::PERIOD_ACTIVE,
+    regs::PERIOD_MONITOR,
+    regs::G_MODE,
+    regs::CTRL,
+    regs::TIME_ENTER_MONITOR,
+    regs::RADIAN_VALUE,
+    regs::OFFSET_LEFT_RIGHT,
+    regs::OFFSET_UP_DOWN,
+    regs::DISTANCE_LEFT_RIGHT,
+    regs::DISTANCE_UP_DOWN,
+    regs::DISTANCE_ZOOM,
+];
+
+/// Contiguous register ranges of CONFIG_REGS read by read_config, start register and length
+pub(crate) const CONFIG_BURSTS: [(u8, usize); 4] = [
+    (regs::THRESHHOLD, 1),
+    (regs::FILTER_COEFF, 5),
+    (regs::RADIAN_VALUE, 6),
+    (regs::G_MODE, 1),
+];
+pub(crate) const MAX_CONFIG_BURST_LEN: usize = 6;
+
+impl Config {
+    /// Register writes for this configuration, None values are skipped
+    pub(crate) fn to_registers(self) -> [Option<(u8, u8)>; CONFIG_REGS.len()] {
+        let gesture = self.gesture.map(|g| [g.min_angle, g.offset_left_right, g.offset_up_down,
+            g.distance_left_right, g.distance_up_down, g.distance_zoom]);
+        let values = [
+            Some(self.threshhold),
+            self.filter_coefficient,
+            self.active_report_rate,
+            self.monitor_report_rate,
+            self.interrupt_mode.map(|m| m as u8),
+            self.auto_monitor_mode.map(|a| a as u8),
+            self.monitor_mode_delay,
+            gesture.map(|g| g[0]),
+            gesture.map(|g| g[1]),
+            gesture.map(|g| g[2]),
+            gesture.map(|g| g[3]),
+            gesture.map(|g| g[4]),
+            gesture.map(|g| g[5]),
+        ];
+        let mut registers = [None; CONFIG_REGS.len()];
+        for (index, register) in registers.iter_mut().enumerate() {
+            *register = values[index].map(|value| (CONFIG_REGS[index], value));
         }
+        registers
     }
 
-    /// get first touch point
-    pub async fn get_point0(&mut self) -> Result<Option<PointEvent>, I2C::Error> {
-        self.get_point(0).await
+    /// Stores the values of a burst read from start_reg at their CONFIG_REGS index
+    pub(crate) fn store_burst(values: &mut [u8; CONFIG_REGS.len()], start_reg: u8, burst: &[u8]) {
+        for (reg_addr, value) in (start_reg..).zip(burst) {
+            if let Some(index) = CONFIG_REGS.iter().position(|r| *r == reg_addr) {
+                values[index] = *value;
+            }
+        }
     }
 
-    /// get second touch point
-    pub async fn get_point1(&mut self) -> Result<Option<PointEvent>, I2C::Error> {
-        self.get_point(1).await
+    /// values read from CONFIG_REGS
+    pub(crate) fn from_registers(values: &[u8; CONFIG_REGS.len()]) -> Self {
+        Config {
+            threshhold: values[0],
+            filter_coefficient: Some(values[1]),
+            active_report_rate: Some(values[2]),
+            monitor_report_rate: Some(values[3]),
+            interrupt_mode: Some(InterruptMode::from_u8(values[4])),
+            auto_monitor_mode: Some(values[5] != 0),
+            monitor_mode_delay: Some(values[6]),
+            gesture: Some(GestureParams {
+                min_angle: values[7],
+                offset_left_right: values[8],
+                offset_up_down: values[9],
+                distance_left_right: values[10],
+                distance_up_down: values[11],
+                distance_zoom: values[12],
+            }),
+        }
     }
+}
 
-    #[inline]
-    pub async fn get_point(&mut self, nth: u8) -> Result<Option<PointEvent>, I2C::Error> {
-        if self.get_number_of_touches().await? <= nth {
-            return Ok(None);
-        }
-        let mut buf = [0u8; 6];
-        self.i2c
-            .write_read(self.addr, &[0x03 + 6 * nth], &mut buf).await?;
+/// Gesture detection parameters, ID_G_RADIAN_VALUE..ID_G_DISTANCE_ZOOM registers
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub struct GestureParams {
+    /// minimal allowed angle for rotating gestures
+    pub min_angle: u8,
+    /// maximum offset for moving left and right gestures
+    pub offset_left_right: u8,
+    /// maximum offset for moving up and down gestures
+    pub offset_up_down: u8,
+    /// minimum distance for moving left and right gestures
+    pub distance_left_right: u8,
+    /// minimum distance for moving up and down gestures
+    pub distance_up_down: u8,
+    /// maximum distance for zoom in and out gestures
+    pub distance_zoom: u8,
+}
 
-        let event = if let Some(event) = EventType::from_u8(buf[0] >> 6) {
-            event
-        } else {
-            return Ok(None);
-        };
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TouchEventKind {
+    Down,
+    Move,
+    Up,
+}
 
-        let x = (((buf[0] as u16) & 0b111) << 8) | (buf[1] as u16);
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TouchEvent {
+    pub kind: TouchEventKind,
+    /// Touch ID, 0 or 1
+    pub touch_id: u8,
+    pub x: u16,
+    pub y: u16,
+}
 
-        let touch_id = buf[2] >> 4;
-        if touch_id == 0x0f {
-            return Ok(None); // invalid touch id
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub struct EventStreamConfig {
+    /// moves below this distance in touch coordinates are not reported
+    pub move_threshold: u16,
+    /// interval to read the touch registers while a finger is down
+    pub poll_interval_ms: u32,
+    /// consecutive reads a touch id must be present before down and absent before up is reported
+    pub debounce_samples: u8,
+}
+
+impl Default for EventStreamConfig {
+    fn default() -> Self {
+        EventStreamConfig {
+            move_threshold: 2,
+            poll_interval_ms: 10,
+            debounce_samples: 1,
         }
-        let y = (((buf[2] as u16) & 0b111) << 8) | (buf[3] as u16);
-        let weight = buf[4];
-        let area = buf[5] & 0b1111;
+    }
+}
 
-        Ok(Some(PointEvent {
-            x,
-            y,
-            event,
-            weight,
-            area,
-            touch_id,
-        }))
+/// Down, move and up events per touch id. Waits for INT low, or for the INT pulse in
+/// InterruptMode::Trigger, while nothing is touched and polls while a finger is down,
+/// so the lift up is always reported even if the panel does not signal it on INT. Down and up are debounced with
+/// debounce_samples, moves are filtered with move_threshold.
+pub struct TouchEventStream<I2C, INT, D> {
+    driver: FT6236<I2C>,
+    int: INT,
+    delay: D,
+    config: EventStreamConfig,
+    active: [Option<(u16, u16)>; 2],
+    /// consecutive reads that disagree with active, per touch id
+    debounce: [u8; 2],
+    pending: [Option<TouchEvent>; 2],
+    /// the last read reported no touch point
+    empty_read: bool,
+}
+
+impl<I2C, INT, D> TouchEventStream<I2C, INT, D>
+where
+    I2C: I2c,
+    INT: Wait,
+    D: embedded_hal_async::delay::DelayNs,
+{
+    pub fn new(driver: FT6236<I2C>, int: INT, delay: D, config: EventStreamConfig) -> Self {
+        TouchEventStream {
+            driver,
+            int,
+            delay,
+            config,
+            active: [None, None],
+            debounce: [0, 0],
+            pending: [None, None],
+            empty_read: false,
+        }
     }
 
-    /// Get the gesture, this is not available for some touch panels
-    pub async fn get_gesture(&mut self) -> Result<Option<Gesture>, I2C::Error> {
-        let gesture = self.read_reg(regs::GEST_ID).await?;
-        Ok(Gesture::from_u8(gesture))
+    pub fn driver(&mut self) -> &mut FT6236<I2C> {
+        &mut self.driver
     }
 
-    async fn read_reg(&mut self, reg_addr: u8) -> Result<u8, I2C::Error> {
-        let mut buf = [0u8];
-        self.i2c.write_read(self.addr, &[reg_addr], &mut buf).await?;
+    pub fn release(self) -> (FT6236<I2C>, INT, D) {
+        (self.driver, self.int, self.delay)
+    }
 
-        Ok(buf[0])
+    /// A finger is down or a down is being debounced
+    fn is_touched(&self) -> bool {
+        self.active.iter().any(|a| a.is_some()) || self.debounce.iter().any(|d| *d > 0)
     }
 
-    async fn write_reg(&mut self, reg_addr: u8, value: u8) -> Result<(), I2C::Error> {
-        self.i2c.write(self.addr, &[reg_addr, value]).await?;
+    pub async fn next_event(&mut self) -> Result<TouchEvent, WakeError<I2C::Error, INT::Error>> {
+        loop {
+            if let Some(event) = self.pending.iter_mut().find_map(|p| p.take()) {
+                return Ok(event);
+            }
+            if self.is_touched() {
+                self.delay.delay_ms(self.config.poll_interval_ms).await;
+            } else if self.driver.config.interrupt_mode == Some(InterruptMode::Trigger) {
+                self.int.wait_for_falling_edge().await.map_err(WakeError::Pin)?;
+            } else {
+                // INT can stay low without a touch point, don't read the registers back to back
+                if self.empty_read {
+                    self.delay.delay_ms(self.config.poll_interval_ms).await;
+                }
+                self.int.wait_for_low().await.map_err(WakeError::Pin)?;
+            }
+            let data = self.driver.read_touch_data().await.map_err(WakeError::I2c)?;
+            self.empty_read = data.points.iter().flatten().all(|p| p.event == EventType::LiftUp);
+            self.process(&data);
+        }
+    }
 
-        Ok(())
+    /// Counts a read that disagrees with the active state, true once it was seen debounce_samples times
+    fn debounced(&mut self, slot: usize) -> bool {
+        self.debounce[slot] += 1;
+        if self.debounce[slot] < self.config.debounce_samples.max(1) {
+            return false;
+        }
+        self.debounce[slot] = 0;
+        true
     }
-}
 
-#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
-pub struct Config {
-    /// threshold for touch detection
-    pub threshhold: u8,
+    fn process(&mut self, data: &TouchData) {
+        for touch_id in 0..2u8 {
+            let point = data.points.iter()
+                .flatten()
+                .find(|p| p.touch_id == touch_id && p.event != EventType::LiftUp);
+            let slot = touch_id as usize;
+            let (kind, point) = match (point, self.active[slot]) {
+                (Some(point), None) => {
+                    if !self.debounced(slot) {
+                        continue;
+                    }
+                    (TouchEventKind::Down, point)
+                }
+                (Some(point), Some((x, y))) => {
+                    self.debounce[slot] = 0;
+                    if point.x.abs_diff(x) < self.config.move_threshold && point.y.abs_diff(y) < self.config.move_threshold {
+                        continue;
+                    }
+                    (TouchEventKind::Move, point)
+                }
+                (None, Some((x, y))) => {
+                    if self.debounced(slot) {
+                        self.active[slot] = None;
+                        self.pending[slot] = Some(TouchEvent { kind: TouchEventKind::Up, touch_id, x, y });
+                    }
+                    continue;
+                }
+                (None, None) => {
+                    self.debounce[slot] = 0;
+                    continue;
+                }
+            };
+            self.active[slot] = Some((point.x, point.y));
+            self.pending[slot] = Some(TouchEvent { kind, touch_id, x: point.x, y: point.y });
+        }
+    }
 }
 
-impl Default for Config {
-    fn default() -> Self {
-        Config { threshhold: 0x40 }
+#[cfg(test)]
+mod tests {
+    use crate::mock_i2c::{block_on, Ft6236Model, MockPin, MockPinError, NoDelay};
+    use crate::touch::{TouchController, TouchPhase};
+
+    use super::*;
+
+    fn touch(x: u16, y: u16) -> PointEvent {
+        PointEvent {
+            x,
+            y,
+            event: EventType::Contact,
+            weight: 20,
+            area: 3,
+            touch_id: 0,
+        }
+    }
+
+    #[test]
+    fn init_and_read_touches() {
+        let mut ft6236 = FT6236::new(Ft6236Model::new_bus(0x06));
+        let config = Config { interrupt_mode: Some(InterruptMode::Trigger), ..Config::default() };
+        block_on(ft6236.init(config)).unwrap();
+        assert_eq!(ft6236.i2c.model().get_register(regs::G_MODE), InterruptMode::Trigger as u8);
+        assert_eq!(block_on(ft6236.read_config()).unwrap().interrupt_mode, Some(InterruptMode::Trigger));
+
+        ft6236.i2c.model().set_touch(0, touch(100, 200));
+        ft6236.i2c.model().set_number_of_touches(1);
+        let points = block_on(TouchController::read_touches(&mut ft6236)).unwrap();
+        assert_eq!(points[0].map(|p| (p.phase, p.x, p.y)), Some((TouchPhase::Down, 100, 200)));
+        assert_eq!(points[1], None);
+    }
+
+    #[test]
+    fn event_stream_delays_reads_while_int_stays_low_without_touch() {
+        let driver = FT6236::new(Ft6236Model::new_bus(0x64));
+        let mut stream = TouchEventStream::new(driver, MockPin::fail_after(3), NoDelay::default(),
+                                               EventStreamConfig::default());
+
+        assert_eq!(block_on(stream.next_event()), Err(WakeError::Pin(MockPinError)));
+        let (driver, int, delay) = stream.release();
+        assert_eq!(driver.i2c.get_transaction_count(), 3);
+        assert_eq!(int.get_wait_for_low_count(), 4);
+        assert_eq!(delay.delay_count, 3);
+    }
+
+    #[test]
+    fn event_stream_waits_for_the_pulse_in_trigger_mode() {
+        let mut driver = FT6236::new(Ft6236Model::new_bus(0x64));
+        driver.config.interrupt_mode = Some(InterruptMode::Trigger);
+        driver.i2c.model().set_touch(0, touch(100, 200));
+        driver.i2c.model().set_number_of_touches(1);
+        let mut stream = TouchEventStream::new(driver, MockPin::fail_after(1), NoDelay::default(),
+                                               EventStreamConfig::default());
+
+        let event = block_on(stream.next_event()).unwrap();
+        assert_eq!(event, TouchEvent { kind: TouchEventKind::Down, touch_id: 0, x: 100, y: 200 });
+        stream.driver().i2c.model().clear_touches();
+        let event = block_on(stream.next_event()).unwrap();
+        assert_eq!(event, TouchEvent { kind: TouchEventKind::Up, touch_id: 0, x: 100, y: 200 });
+
+        let (_, int, delay) = stream.release();
+        assert_eq!(int.get_wait_for_falling_edge_count(), 1);
+        assert_eq!(int.get_wait_for_low_count(), 0);
+        assert_eq!(delay.delay_count, 1);
     }
 }
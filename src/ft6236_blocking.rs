@@ -0,0 +1,162 @@
+use embedded_hal::delay::DelayNs;
+use embedded_hal::digital::OutputPin;
+use embedded_hal::i2c::I2c;
+
+use crate::ft6236_asynch::{CHIP_INFO_REGS, CONFIG_BURSTS, CONFIG_REGS, ChipId, ChipInfo, Config, Error, FT6236_DEFAULT_ADDR,
+                           ft6236_driver, Gesture, MAX_CONFIG_BURST_LEN, PointEvent, PowerMode, regs, TOUCH_DATA_LEN,
+                           TouchData, WakeError};
+use crate::touch::TouchTracker;
+
+/// Blocking variant of ft6236_asynch::FT6236, the methods are generated from the same ft6236_driver macro
+pub struct FT6236<I2C> {
+    i2c: I2C,
+    addr: u8,
+    config: Config,
+    power_mode: PowerMode,
+    pub(crate) tracker: TouchTracker,
+}
+
+ft6236_driver!(;);
+
+#[cfg(test)]
+mod tests {
+    use crate::ft6236_asynch::{EventType, InterruptMode};
+    use crate::mock_i2c::{Ft6236Model, MockI2cError, MockPin, NoDelay, ScriptedI2c, Transaction};
+    use crate::touch::{BlockingTouchController, TouchPhase};
+
+    use super::*;
+
+    fn point(x: u16, y: u16, event: EventType, touch_id: u8) -> PointEvent {
+        PointEvent {
+            x,
+            y,
+            event,
+            weight: 20,
+            area: 3,
+            touch_id,
+        }
+    }
+
+    #[test]
+    fn init_rejects_unknown_chip_id() {
+        let mut ft6236 = FT6236::new(Ft6236Model::new_bus(0x42));
+        assert_eq!(ft6236.init(Config::default()), Err(Error::InvalidChipId(0x42)));
+        // nothing is written to an unknown chip
+        assert_eq!(ft6236.i2c.model().get_register(0x80), 0);
+
+        let mut ft6236 = FT6236::new(Ft6236Model::new_bus(0x36));
+        ft6236.init(Config { threshhold: 0x28, ..Config::default() }).unwrap();
+        assert_eq!(ft6236.i2c.model().get_register(0x80), 0x28);
+    }
+
+    #[test]
+    fn write_config_writes_only_the_set_registers() {
+        let addr = FT6236_DEFAULT_ADDR;
+        let bus = ScriptedI2c::new(&[
+            Transaction::Write(addr, [0x80, 0x30].to_vec()),
+            Transaction::Write(addr, [0x88, 0x0C].to_vec()),
+            Transaction::Write(addr, [0xA4, 0x01].to_vec()),
+            Transaction::Write(addr, [0x86, 0x00].to_vec()),
+        ]);
+        let mut ft6236 = FT6236::new(bus);
+        ft6236.write_config(Config {
+            threshhold: 0x30,
+            active_report_rate: Some(0x0C),
+            interrupt_mode: Some(InterruptMode::Trigger),
+            auto_monitor_mode: Some(false),
+            ..Config::default()
+        }).unwrap();
+        ft6236.i2c.done();
+    }
+
+    #[test]
+    fn reports_bus_errors() {
+        let mut bus = Ft6236Model::new_bus(0x64);
+        bus.model().set_touch(0, point(100, 200, EventType::PressDown, 0));
+        bus.model().set_number_of_touches(1);
+        let mut ft6236 = FT6236::new(bus);
+
+        ft6236.i2c.fail_next();
+        assert_eq!(ft6236.init(Config::default()), Err(Error::I2c(MockI2cError::Bus)));
+        ft6236.i2c.fail_next();
+        assert_eq!(ft6236.read_touches(), Err(MockI2cError::Bus));
+        // the failed read does not change the touch state
+        assert_eq!(ft6236.read_touches().unwrap()[0].unwrap().phase, TouchPhase::Down);
+    }
+
+    #[test]
+    fn decodes_both_touch_points() {
+        let mut bus = Ft6236Model::new_bus(0x64);
+        bus.model().set_touch(0, point(0x7FF, 12, EventType::PressDown, 0));
+        bus.model().set_touch(1, point(300, 0x7FF, EventType::Contact, 1));
+        bus.model().set_number_of_touches(2);
+        bus.model().set_gesture(Some(Gesture::MoveUp));
+        let mut ft6236 = FT6236::new(bus);
+
+        let data = ft6236.read_touch_data().unwrap();
+        assert_eq!(data.number_of_touches, 2);
+        assert_eq!(data.gesture, Some(Gesture::MoveUp));
+        assert_eq!(data.points, [Some(point(0x7FF, 12, EventType::PressDown, 0)),
+            Some(point(300, 0x7FF, EventType::Contact, 1))]);
+        assert_eq!(ft6236.get_point(1).unwrap(), Some(point(300, 0x7FF, EventType::Contact, 1)));
+        assert_eq!(ft6236.get_point(2).unwrap(), None);
+    }
+
+    #[test]
+    fn rejects_invalid_touch_id() {
+        let mut bus = Ft6236Model::new_bus(0x64);
+        bus.model().set_touch(0, point(100, 100, EventType::Contact, 0x0F));
+        bus.model().set_number_of_touches(1);
+        let mut ft6236 = FT6236::new(bus);
+
+        assert_eq!(ft6236.get_point0().unwrap(), None);
+    }
+
+    #[test]
+    fn rejects_more_than_two_touches() {
+        let mut bus = Ft6236Model::new_bus(0x64);
+        bus.model().set_touch(0, point(100, 100, EventType::Contact, 0));
+        let mut ft6236 = FT6236::new(bus);
+
+        for number_of_touches in [3, 5, 0x0F] {
+            ft6236.i2c.model().set_number_of_touches(number_of_touches);
+            let data = ft6236.read_touch_data().unwrap();
+            assert_eq!(data.number_of_touches, 0);
+            assert_eq!(data.points, [None, None]);
+            assert_eq!(ft6236.get_number_of_touches().unwrap(), 0);
+        }
+    }
+
+    #[test]
+    fn read_touches_reports_up_after_lift() {
+        let mut bus = Ft6236Model::new_bus(0x64);
+        bus.model().set_touch(0, point(100, 200, EventType::PressDown, 0));
+        bus.model().set_number_of_touches(1);
+        let mut ft6236 = FT6236::new(bus);
+
+        let phase = |points: crate::touch::TouchPoints| points[0].map(|p| (p.phase, p.x, p.y));
+        assert_eq!(phase(ft6236.read_touches().unwrap()), Some((TouchPhase::Down, 100, 200)));
+        ft6236.i2c.model().set_touch(0, point(110, 200, EventType::Contact, 0));
+        assert_eq!(phase(ft6236.read_touches().unwrap()), Some((TouchPhase::Move, 110, 200)));
+        ft6236.i2c.model().set_touch(0, point(110, 200, EventType::LiftUp, 0));
+        assert_eq!(phase(ft6236.read_touches().unwrap()), Some((TouchPhase::Up, 110, 200)));
+        ft6236.i2c.model().clear_touches();
+        assert_eq!(phase(ft6236.read_touches().unwrap()), None);
+    }
+
+    #[test]
+    fn wake_with_int_reports_a_held_touch_as_down() {
+        let mut bus = Ft6236Model::new_bus(0x64);
+        bus.model().set_touch(0, point(100, 200, EventType::Contact, 0));
+        bus.model().set_number_of_touches(1);
+        let mut ft6236 = FT6236::new(bus);
+        assert_eq!(ft6236.read_touches().unwrap()[0].unwrap().phase, TouchPhase::Down);
+
+        ft6236.hibernate().unwrap();
+        let mut int = MockPin::new();
+        ft6236.wake_with_int(&mut int, &mut NoDelay::default()).unwrap();
+        assert_eq!(int.get_set_low_count(), 1);
+        assert!(int.is_high());
+        assert_eq!(ft6236.read_touches().unwrap()[0].unwrap().phase, TouchPhase::Down);
+    }
+}
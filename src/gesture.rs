@@ -0,0 +1,316 @@
+use embedded_graphics::geometry::Point;
+
+use crate::ft6236_asynch::{EventType, PointEvent};
+use crate::navigation::SwipeDirection;
+use crate::touch::{TouchPhase, TouchPoints};
+
+/// Touch state at a point in time, up to two touch points
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct GestureSample {
+    pub points: [Option<Point>; 2],
+    pub time_ms: u64,
+}
+
+impl GestureSample {
+    pub fn released(time_ms: u64) -> Self {
+        GestureSample {
+            points: [None, None],
+            time_ms,
+        }
+    }
+
+    pub fn single(pos: Point, time_ms: u64) -> Self {
+        GestureSample {
+            points: [Some(pos), None],
+            time_ms,
+        }
+    }
+
+    pub fn dual(pos0: Point, pos1: Point, time_ms: u64) -> Self {
+        GestureSample {
+            points: [Some(pos0), Some(pos1)],
+            time_ms,
+        }
+    }
+
+    /// Lift up events are treated as released
+    pub fn from_point_events(point0: Option<PointEvent>, point1: Option<PointEvent>, time_ms: u64) -> Self {
+        let to_pos = |point: Option<PointEvent>| {
+            point.filter(|p| p.event != EventType::LiftUp).map(|p| Point::new(p.x as i32, p.y as i32))
+        };
+        GestureSample {
+            points: [to_pos(point0), to_pos(point1)],
+            time_ms,
+        }
+    }
+
+    /// First two touch points of a TouchController read, Up points are treated as released
+    pub fn from_touch_points(points: &TouchPoints, time_ms: u64) -> Self {
+        let mut sample = GestureSample::released(time_ms);
+        let touched = points.iter().flatten().filter(|p| p.phase != TouchPhase::Up);
+        for (pos, point) in sample.points.iter_mut().zip(touched) {
+            *pos = Some(Point::new(point.x as i32, point.y as i32));
+        }
+        sample
+    }
+
+    /// Takes the (x, y, z1) tuple of Tsc2007::touch, z1 below min_pressure is treated as released
+    pub fn from_tsc2007_touch(touch: (u16, u16, u16), min_pressure: u16, time_ms: u64) -> Self {
+        if touch.2 > min_pressure {
+            GestureSample::single(Point::new(touch.0 as i32, touch.1 as i32), time_ms)
+        } else {
+            GestureSample::released(time_ms)
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum GestureEvent {
+    Tap(Point),
+    /// second tap, the first one was already reported as Tap
+    DoubleTap(Point),
+    LongPress(Point),
+    /// velocity in pixels per second
+    Swipe { direction: SwipeDirection, velocity: u32 },
+    Drag { pos: Point, delta: Point },
+    /// finger lifted after a drag that was not a swipe
+    DragEnd(Point),
+    /// scale is the current finger distance relative to the distance at the start of the pinch
+    Pinch { center: Point, scale: f32 },
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct GestureConfig {
+    /// max movement of a tap or long press
+    pub tap_max_distance: u32,
+    pub tap_max_duration_ms: u64,
+    pub double_tap_max_interval_ms: u64,
+    pub long_press_min_duration_ms: u64,
+    pub swipe_min_distance: u32,
+    pub swipe_max_duration_ms: u64,
+    /// finger distance change between two pinch events
+    pub pinch_min_distance_change: u32,
+}
+
+impl Default for GestureConfig {
+    fn default() -> Self {
+        GestureConfig {
+            tap_max_distance: 10,
+            tap_max_duration_ms: 300,
+            double_tap_max_interval_ms: 400,
+            long_press_min_duration_ms: 800,
+            swipe_min_distance: 50,
+            swipe_max_duration_ms: 500,
+            pinch_min_distance_change: 10,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+struct TouchState {
+    start_pos: Point,
+    start_ms: u64,
+    last_pos: Point,
+    last_ms: u64,
+    moved: bool,
+    long_press_sent: bool,
+    multi_touch: bool,
+}
+
+#[derive(Copy, Clone, Debug)]
+struct PinchState {
+    initial_distance: u32,
+    last_distance: u32,
+}
+
+fn get_distance(a: Point, b: Point) -> u32 {
+    let delta = a - b;
+    let square = (delta.x as i64 * delta.x as i64 + delta.y as i64 * delta.y as i64) as u64;
+    isqrt(square) as u32
+}
+
+/// Square root rounded down with Newton's method, u64::isqrt needs Rust 1.84
+fn isqrt(value: u64) -> u64 {
+    if value < 2 {
+        return value;
+    }
+    let mut x = value;
+    let mut y = value / 2 + value % 2;
+    while y < x {
+        x = y;
+        y = (x + value / x) / 2;
+    }
+    x
+}
+
+pub struct GestureRecognizer {
+    config: GestureConfig,
+    touch: Option<TouchState>,
+    pinch: Option<PinchState>,
+    last_tap: Option<(Point, u64)>,
+}
+
+impl GestureRecognizer {
+    pub fn new(config: GestureConfig) -> Self {
+        GestureRecognizer {
+            config,
+            touch: None,
+            pinch: None,
+            last_tap: None,
+        }
+    }
+
+    pub fn get_config(&self) -> GestureConfig {
+        self.config
+    }
+
+    pub fn set_config(&mut self, config: GestureConfig) {
+        self.config = config;
+    }
+
+    pub fn reset(&mut self) {
+        self.touch = None;
+        self.pinch = None;
+        self.last_tap = None;
+    }
+
+    /// Samples must be passed in time order, also while nothing is touched so
+    /// that long press and release are detected in time
+    pub fn update(&mut self, sample: GestureSample) -> Option<GestureEvent> {
+        if let [Some(pos0), Some(pos1)] = sample.points {
+            return self.update_dual(pos0, pos1, sample.time_ms);
+        }
+        self.pinch = None;
+
+        match (sample.points[0].or(sample.points[1]), self.touch.as_mut()) {
+            (Some(pos), None) => {
+                self.touch = Some(TouchState {
+                    start_pos: pos,
+                    start_ms: sample.time_ms,
+                    last_pos: pos,
+                    last_ms: sample.time_ms,
+                    moved: false,
+                    long_press_sent: false,
+                    multi_touch: false,
+                });
+                None
+            }
+            (Some(pos), Some(touch)) => {
+                let delta = pos - touch.last_pos;
+                touch.last_pos = pos;
+                touch.last_ms = sample.time_ms;
+                if touch.multi_touch || touch.long_press_sent {
+                    return None;
+                }
+                if !touch.moved && get_distance(pos, touch.start_pos) > self.config.tap_max_distance {
+                    touch.moved = true;
+                }
+                if touch.moved {
+                    if delta != Point::zero() {
+                        return Some(GestureEvent::Drag { pos, delta });
+                    }
+                    return None;
+                }
+                if sample.time_ms.saturating_sub(touch.start_ms) >= self.config.long_press_min_duration_ms {
+                    touch.long_press_sent = true;
+                    return Some(GestureEvent::LongPress(touch.start_pos));
+                }
+                None
+            }
+            (None, Some(_)) => {
+                let touch = self.touch.take()?;
+                self.release(touch, sample.time_ms)
+            }
+            (None, None) => None,
+        }
+    }
+
+    fn update_dual(&mut self, pos0: Point, pos1: Point, time_ms: u64) -> Option<GestureEvent> {
+        match self.touch.as_mut() {
+            Some(touch) => touch.multi_touch = true,
+            None => {
+                self.touch = Some(TouchState {
+                    start_pos: pos0,
+                    start_ms: time_ms,
+                    last_pos: pos0,
+                    last_ms: time_ms,
+                    moved: false,
+                    long_press_sent: false,
+                    multi_touch: true,
+                });
+            }
+        }
+
+        let distance = get_distance(pos0, pos1);
+        match self.pinch.as_mut() {
+            None => {
+                self.pinch = Some(PinchState {
+                    initial_distance: distance.max(1),
+                    last_distance: distance,
+                });
+                None
+            }
+            Some(pinch) => {
+                if distance.abs_diff(pinch.last_distance) < self.config.pinch_min_distance_change {
+                    return None;
+                }
+                pinch.last_distance = distance;
+                Some(GestureEvent::Pinch {
+                    center: Point::new((pos0.x + pos1.x) / 2, (pos0.y + pos1.y) / 2),
+                    scale: distance as f32 / pinch.initial_distance as f32,
+                })
+            }
+        }
+    }
+
+    fn release(&mut self, touch: TouchState, time_ms: u64) -> Option<GestureEvent> {
+        if touch.multi_touch || touch.long_press_sent {
+            return None;
+        }
+        let duration_ms = touch.last_ms.saturating_sub(touch.start_ms);
+        if touch.moved {
+            if duration_ms <= self.config.swipe_max_duration_ms {
+                if let Some(direction) = SwipeDirection::from_delta(touch.last_pos - touch.start_pos, self.config.swipe_min_distance) {
+                    let distance = get_distance(touch.last_pos, touch.start_pos) as u64;
+                    let velocity = (distance * 1000 / duration_ms.max(1)) as u32;
+                    return Some(GestureEvent::Swipe { direction, velocity });
+                }
+            }
+            return Some(GestureEvent::DragEnd(touch.last_pos));
+        }
+        if duration_ms > self.config.tap_max_duration_ms {
+            return None;
+        }
+        if let Some((tap_pos, tap_ms)) = self.last_tap.take() {
+            if time_ms.saturating_sub(tap_ms) <= self.config.double_tap_max_interval_ms
+                && get_distance(tap_pos, touch.start_pos) <= self.config.tap_max_distance {
+                return Some(GestureEvent::DoubleTap(touch.start_pos));
+            }
+        }
+        self.last_tap = Some((touch.start_pos, time_ms));
+        Some(GestureEvent::Tap(touch.start_pos))
+    }
+}
+
+impl Default for GestureRecognizer {
+    fn default() -> Self {
+        GestureRecognizer::new(GestureConfig::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn isqrt_rounds_down() {
+        for (value, root) in [(0, 0), (1, 1), (2, 1), (3, 1), (4, 2), (15, 3), (16, 4), (17, 4), (99, 9)] {
+            assert_eq!(isqrt(value), root, "isqrt({})", value);
+        }
+        let max_root = u32::MAX as u64;
+        assert_eq!(isqrt(max_root * max_root), max_root);
+        assert_eq!(isqrt(max_root * max_root - 1), max_root - 1);
+        assert_eq!(isqrt(u64::MAX), max_root);
+        assert_eq!(get_distance(Point::new(-3, 0), Point::new(0, 4)), 5);
+    }
+}
@@ -74,6 +74,14 @@ impl GraphicUtils {
         }
         alloc::string::String::from(text)
     }
+    /// None if the text does not fit and width is too narrow for the ellipsis
+    pub fn get_text_with_ellipsis_checked(width: u32, text: &str, font: &MonoFont) -> Option<alloc::string::String> {
+        let character_width = font.character_size.width.max(1);
+        if character_width * text.len() as u32 > width && width / character_width < 3 {
+            return None;
+        }
+        Some(GraphicUtils::get_text_with_ellipsis_from_str(width, text, font))
+    }
 }
 
 pub trait ListItem {
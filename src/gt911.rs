@@ -0,0 +1,432 @@
+use embedded_hal::delay::DelayNs;
+use embedded_hal::digital::OutputPin;
+use embedded_hal_async::i2c::I2c;
+
+use crate::touch::TouchTracker;
+
+/// Address when INT is low while RST is released
+pub const GT911_DEFAULT_ADDR: u8 = 0x5D;
+/// Address when INT is high while RST is released
+pub const GT911_ALTERNATE_ADDR: u8 = 0x14;
+
+pub mod regs {
+    pub const COMMAND: u16 = 0x8040;
+    pub const CONFIG_VERSION: u16 = 0x8047;
+    pub const X_OUTPUT_MAX: u16 = 0x8048;
+    pub const Y_OUTPUT_MAX: u16 = 0x804A;
+    pub const TOUCH_NUMBER: u16 = 0x804C;
+    pub const MODULE_SWITCH1: u16 = 0x804D;
+    pub const CONFIG_CHECKSUM: u16 = 0x80FF;
+    pub const CONFIG_FRESH: u16 = 0x8100;
+    pub const PRODUCT_ID: u16 = 0x8140;
+    pub const FIRMWARE_VERSION: u16 = 0x8144;
+    pub const X_RESOLUTION: u16 = 0x8146;
+    pub const Y_RESOLUTION: u16 = 0x8148;
+    pub const VENDOR_ID: u16 = 0x814A;
+    pub const STATUS: u16 = 0x814E;
+    pub const POINT1: u16 = 0x814F;
+}
+
+/// CONFIG_VERSION..0x80FE, the checksum follows directly
+pub const CONFIG_LEN: usize = 184;
+pub const MAX_TOUCH_POINTS: usize = 5;
+const TOUCH_POINT_LEN: usize = 8;
+/// PRODUCT_ID..VENDOR_ID
+const PRODUCT_INFO_LEN: usize = 11;
+
+const STATUS_BUFFER_READY: u8 = 0x80;
+const COMMAND_SOFT_RESET: u8 = 0x02;
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error<E> {
+    I2c(E),
+    /// PRODUCT_ID did not start with "9"
+    InvalidProductId([u8; 4]),
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Address {
+    /// 0x5D
+    Default,
+    /// 0x14
+    Alternate,
+}
+
+impl Address {
+    pub fn addr(&self) -> u8 {
+        match self {
+            Address::Default => GT911_DEFAULT_ADDR,
+            Address::Alternate => GT911_ALTERNATE_ADDR,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum InterruptTrigger {
+    RisingEdge = 0x00,
+    FallingEdge = 0x01,
+    LowLevel = 0x02,
+    HighLevel = 0x03,
+}
+
+impl InterruptTrigger {
+    fn from_u8(trigger: u8) -> Self {
+        match trigger & 0b11 {
+            0x00 => InterruptTrigger::RisingEdge,
+            0x01 => InterruptTrigger::FallingEdge,
+            0x02 => InterruptTrigger::LowLevel,
+            _ => InterruptTrigger::HighLevel,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ProductInfo {
+    /// ASCII, e.g. "911\0"
+    pub product_id: [u8; 4],
+    pub firmware_version: u16,
+    pub x_resolution: u16,
+    pub y_resolution: u16,
+    pub vendor_id: u8,
+}
+
+impl ProductInfo {
+    /// decode PRODUCT_ID..VENDOR_ID
+    fn from_registers(buf: &[u8; PRODUCT_INFO_LEN]) -> Self {
+        ProductInfo {
+            product_id: [buf[0], buf[1], buf[2], buf[3]],
+            firmware_version: u16::from_le_bytes([buf[4], buf[5]]),
+            x_resolution: u16::from_le_bytes([buf[6], buf[7]]),
+            y_resolution: u16::from_le_bytes([buf[8], buf[9]]),
+            vendor_id: buf[10],
+        }
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TouchPoint {
+    /// Track ID, stays the same while the finger is down
+    pub track_id: u8,
+    pub x: u16,
+    pub y: u16,
+    /// Touch area
+    pub size: u16,
+}
+
+impl TouchPoint {
+    fn from_registers(buf: &[u8]) -> Self {
+        TouchPoint {
+            track_id: buf[0],
+            x: u16::from_le_bytes([buf[1], buf[2]]),
+            y: u16::from_le_bytes([buf[3], buf[4]]),
+            size: u16::from_le_bytes([buf[5], buf[6]]),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TouchData {
+    /// Number of touches, 0 to 5
+    pub number_of_touches: u8,
+    /// Only the first number_of_touches points are set
+    pub points: [Option<TouchPoint>; MAX_TOUCH_POINTS],
+}
+
+/// Config block CONFIG_VERSION..0x80FE, read it with read_config and change the fields
+/// needed, writing a block built from scratch may leave the panel unusable
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct Config {
+    data: [u8; CONFIG_LEN],
+}
+
+impl Config {
+    pub fn from_registers(data: &[u8; CONFIG_LEN]) -> Self {
+        Config { data: *data }
+    }
+
+    pub fn as_registers(&self) -> &[u8; CONFIG_LEN] {
+        &self.data
+    }
+
+    fn offset(reg_addr: u16) -> usize {
+        (reg_addr - regs::CONFIG_VERSION) as usize
+    }
+
+    fn get_u16(&self, reg_addr: u16) -> u16 {
+        let offset = Self::offset(reg_addr);
+        u16::from_le_bytes([self.data[offset], self.data[offset + 1]])
+    }
+
+    fn set_u16(&mut self, reg_addr: u16, value: u16) {
+        let offset = Self::offset(reg_addr);
+        self.data[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+    }
+
+    /// The chip only accepts a config with a version greater or equal to its current one
+    pub fn get_version(&self) -> u8 {
+        self.data[Self::offset(regs::CONFIG_VERSION)]
+    }
+
+    pub fn set_version(&mut self, version: u8) {
+        self.data[Self::offset(regs::CONFIG_VERSION)] = version;
+    }
+
+    /// Reported coordinates are scaled to 0..resolution
+    pub fn get_resolution(&self) -> (u16, u16) {
+        (self.get_u16(regs::X_OUTPUT_MAX), self.get_u16(regs::Y_OUTPUT_MAX))
+    }
+
+    pub fn set_resolution(&mut self, x: u16, y: u16) {
+        self.set_u16(regs::X_OUTPUT_MAX, x);
+        self.set_u16(regs::Y_OUTPUT_MAX, y);
+    }
+
+    pub fn get_touch_number(&self) -> u8 {
+        self.data[Self::offset(regs::TOUCH_NUMBER)] & 0x0F
+    }
+
+    /// Maximum number of touch points, 1 to 5
+    pub fn set_touch_number(&mut self, touch_number: u8) {
+        let offset = Self::offset(regs::TOUCH_NUMBER);
+        self.data[offset] = (self.data[offset] & 0xF0) | touch_number.clamp(1, MAX_TOUCH_POINTS as u8);
+    }
+
+    pub fn get_interrupt_trigger(&self) -> InterruptTrigger {
+        InterruptTrigger::from_u8(self.data[Self::offset(regs::MODULE_SWITCH1)])
+    }
+
+    pub fn set_interrupt_trigger(&mut self, trigger: InterruptTrigger) {
+        let offset = Self::offset(regs::MODULE_SWITCH1);
+        self.data[offset] = (self.data[offset] & !0b11) | trigger as u8;
+    }
+
+    pub fn get_swap_xy(&self) -> bool {
+        self.data[Self::offset(regs::MODULE_SWITCH1)] & 0b1000 != 0
+    }
+
+    pub fn set_swap_xy(&mut self, swap_xy: bool) {
+        let offset = Self::offset(regs::MODULE_SWITCH1);
+        if swap_xy {
+            self.data[offset] |= 0b1000;
+        } else {
+            self.data[offset] &= !0b1000;
+        }
+    }
+
+    /// Two's complement of the byte sum, written to CONFIG_CHECKSUM
+    pub fn checksum(&self) -> u8 {
+        let sum = self.data.iter().fold(0u8, |sum, b| sum.wrapping_add(*b));
+        (!sum).wrapping_add(1)
+    }
+}
+
+pub struct GT911<I2C> {
+    i2c: I2C,
+    addr: u8,
+    pub(crate) tracker: TouchTracker,
+}
+
+impl<I2C> GT911<I2C>
+where
+    I2C: I2c,
+{
+    pub fn new(i2c: I2C) -> Self {
+        GT911::new_with_addr(i2c, GT911_DEFAULT_ADDR)
+    }
+
+    pub fn new_with_addr(i2c: I2C, addr: u8) -> Self {
+        GT911 {
+            i2c,
+            addr,
+            tracker: TouchTracker::new(),
+        }
+    }
+
+    pub fn addr(&self) -> u8 {
+        self.addr
+    }
+
+    /// Reset and select the I2C address with the level of INT while RST is released.
+    /// int must be the INT line configured as output, switch it back to a floating
+    /// input afterwards so the chip can drive it
+    pub fn reset<R: OutputPin, P: OutputPin<Error = R::Error>, D: DelayNs>(
+        &mut self,
+        rst: &mut R,
+        int: &mut P,
+        delay: &mut D,
+        address: Address,
+    ) -> Result<(), R::Error> {
+        rst.set_low()?;
+        int.set_low()?;
+        delay.delay_ms(10); // min = 100us
+        if address == Address::Alternate {
+            int.set_high()?;
+        }
+        delay.delay_ms(1); // min = 100us
+        rst.set_high()?;
+        delay.delay_ms(6); // min = 5ms
+        int.set_low()?;
+        delay.delay_ms(55); // min = 50ms
+        self.addr = address.addr();
+        self.tracker = TouchTracker::new();
+
+        Ok(())
+    }
+
+    /// Read and check the product info
+    pub async fn init(&mut self) -> Result<ProductInfo, Error<I2C::Error>> {
+        let info = self.read_product_info().await.map_err(Error::I2c)?;
+        if info.product_id[0] != b'9' {
+            #[cfg(feature = "defmt")]
+            defmt::error!("invalid product id {=[u8]}", info.product_id);
+            return Err(Error::InvalidProductId(info.product_id));
+        }
+
+        #[cfg(feature = "defmt")]
+        defmt::info!("firmware 0x{:04x}, resolution {}x{}", info.firmware_version, info.x_resolution, info.y_resolution);
+
+        Ok(info)
+    }
+
+    pub async fn read_product_info(&mut self) -> Result<ProductInfo, I2C::Error> {
+        let mut buf = [0u8; PRODUCT_INFO_LEN];
+        self.read_regs(regs::PRODUCT_ID, &mut buf).await?;
+        Ok(ProductInfo::from_registers(&buf))
+    }
+
+    pub async fn read_config(&mut self) -> Result<Config, I2C::Error> {
+        let mut buf = [0u8; CONFIG_LEN];
+        self.read_regs(regs::CONFIG_VERSION, &mut buf).await?;
+        Ok(Config::from_registers(&buf))
+    }
+
+    /// Write the config block with its checksum and tell the chip to apply it
+    pub async fn write_config(&mut self, config: &Config) -> Result<(), I2C::Error> {
+        let mut buf = [0u8; 2 + CONFIG_LEN + 1];
+        buf[..2].copy_from_slice(&regs::CONFIG_VERSION.to_be_bytes());
+        buf[2..2 + CONFIG_LEN].copy_from_slice(config.as_registers());
+        buf[2 + CONFIG_LEN] = config.checksum();
+        self.i2c.write(self.addr, &buf).await?;
+        self.write_reg(regs::CONFIG_FRESH, 1).await
+    }
+
+    /// Change the reported coordinate range, the chip keeps it until the next power cycle
+    pub async fn set_resolution(&mut self, x: u16, y: u16) -> Result<(), I2C::Error> {
+        let mut config = self.read_config().await?;
+        config.set_resolution(x, y);
+        self.write_config(&config).await
+    }
+
+    pub async fn soft_reset(&mut self) -> Result<(), I2C::Error> {
+        self.write_reg(regs::COMMAND, COMMAND_SOFT_RESET).await
+    }
+
+    /// None if there is no new report since the last read
+    pub async fn read_touch_data(&mut self) -> Result<Option<TouchData>, I2C::Error> {
+        let mut status = [0u8];
+        self.read_regs(regs::STATUS, &mut status).await?;
+        if status[0] & STATUS_BUFFER_READY == 0 {
+            return Ok(None);
+        }
+
+        let mut number_of_touches = status[0] & 0x0F;
+        if number_of_touches as usize > MAX_TOUCH_POINTS {
+            number_of_touches = 0; // invalid
+        }
+        let mut buf = [0u8; TOUCH_POINT_LEN * MAX_TOUCH_POINTS];
+        let len = TOUCH_POINT_LEN * number_of_touches as usize;
+        if len > 0 {
+            self.read_regs(regs::POINT1, &mut buf[..len]).await?;
+        }
+        // the chip does not update the buffer until the status is cleared
+        self.write_reg(regs::STATUS, 0).await?;
+
+        let mut points = [None; MAX_TOUCH_POINTS];
+        for (nth, point) in points.iter_mut().enumerate().take(number_of_touches as usize) {
+            let start = TOUCH_POINT_LEN * nth;
+            *point = Some(TouchPoint::from_registers(&buf[start..start + TOUCH_POINT_LEN]));
+        }
+        Ok(Some(TouchData {
+            number_of_touches,
+            points,
+        }))
+    }
+
+    async fn read_regs(&mut self, reg_addr: u16, buf: &mut [u8]) -> Result<(), I2C::Error> {
+        self.i2c.write_read(self.addr, &reg_addr.to_be_bytes(), buf).await
+    }
+
+    async fn write_reg(&mut self, reg_addr: u16, value: u8) -> Result<(), I2C::Error> {
+        let [high, low] = reg_addr.to_be_bytes();
+        self.i2c.write(self.addr, &[high, low, value]).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mock_i2c::{block_on, ScriptedI2c, Transaction};
+
+    use super::*;
+
+    #[test]
+    fn decodes_product_info() {
+        let info = ProductInfo::from_registers(&[b'9', b'1', b'1', 0, 0x60, 0x10, 0xE0, 0x01, 0x10, 0x01, 0x02]);
+        assert_eq!(info, ProductInfo {
+            product_id: *b"911\0",
+            firmware_version: 0x1060,
+            x_resolution: 480,
+            y_resolution: 272,
+            vendor_id: 2,
+        });
+    }
+
+    #[test]
+    fn config_fields_and_checksum() {
+        let mut data = [0u8; CONFIG_LEN];
+        data.iter_mut().enumerate().for_each(|(index, b)| *b = index as u8);
+        let mut config = Config::from_registers(&data);
+        config.set_resolution(800, 480);
+        config.set_touch_number(9);
+        config.set_interrupt_trigger(InterruptTrigger::LowLevel);
+        config.set_swap_xy(true);
+
+        assert_eq!(config.get_resolution(), (800, 480));
+        assert_eq!(config.get_touch_number(), MAX_TOUCH_POINTS as u8);
+        assert_eq!(config.get_interrupt_trigger(), InterruptTrigger::LowLevel);
+        assert!(config.get_swap_xy());
+        let sum = config.as_registers().iter().fold(config.checksum(), |sum, b| sum.wrapping_add(*b));
+        assert_eq!(sum, 0);
+    }
+
+    #[test]
+    fn reads_touch_points_and_clears_the_status() {
+        let addr = GT911_DEFAULT_ADDR;
+        let mut points = [0u8; 2 * TOUCH_POINT_LEN];
+        points[..7].copy_from_slice(&[3, 0x20, 0x01, 0x40, 0x00, 0x18, 0x00]);
+        points[8..15].copy_from_slice(&[4, 0x10, 0x00, 0x05, 0x01, 0x08, 0x00]);
+        let mut gt911 = GT911::new(ScriptedI2c::new(&[
+            Transaction::Write(addr, regs::STATUS.to_be_bytes().to_vec()),
+            Transaction::Read(addr, [STATUS_BUFFER_READY | 2].to_vec()),
+            Transaction::Write(addr, regs::POINT1.to_be_bytes().to_vec()),
+            Transaction::Read(addr, points.to_vec()),
+            Transaction::Write(addr, [0x81, 0x4E, 0].to_vec()),
+            Transaction::Write(addr, regs::STATUS.to_be_bytes().to_vec()),
+            Transaction::Read(addr, [0].to_vec()),
+        ]));
+
+        let data = block_on(gt911.read_touch_data()).unwrap().unwrap();
+        assert_eq!(data.number_of_touches, 2);
+        assert_eq!(data.points[0], Some(TouchPoint { track_id: 3, x: 288, y: 64, size: 24 }));
+        assert_eq!(data.points[1], Some(TouchPoint { track_id: 4, x: 16, y: 261, size: 8 }));
+        assert_eq!(data.points[2], None);
+        assert_eq!(block_on(gt911.read_touch_data()).unwrap(), None);
+        gt911.i2c.done();
+    }
+}
@@ -2,11 +2,30 @@
 
 pub mod dummy_pin;
 pub mod touch_mapper;
+pub mod touch;
+pub mod touch_filter;
+pub mod touch_record;
 pub mod fonts;
 pub mod sdcard;
+pub mod settings;
 pub mod tsc2007;
+pub mod tsc2007_blocking;
+pub mod resistive_touch;
+pub mod xpt2046;
 pub mod png;
 pub mod jpeg;
 pub mod ft6236_asynch;
+pub mod ft6236_blocking;
+pub mod gt911;
+pub mod cst816;
+#[cfg(any(test, feature = "mock"))]
+pub mod mock_i2c;
 
 pub mod graphics;
+pub mod memory_display;
+pub mod dialog;
+pub mod seven_segment;
+pub mod chart;
+pub mod navigation;
+pub mod gesture;
+pub mod calibration;
@@ -0,0 +1,72 @@
+extern crate alloc;
+
+use alloc::vec::Vec;
+use core::convert::Infallible;
+
+use embedded_graphics::draw_target::DrawTarget;
+use embedded_graphics::geometry::{OriginDimensions, Point, Size};
+use embedded_graphics::Pixel;
+use embedded_graphics::pixelcolor::Rgb565;
+use embedded_graphics::prelude::RgbColor;
+
+/// Frame buffer DrawTarget, e.g. to check the drawing of widgets on a host
+pub struct MemoryDisplay {
+    size: Size,
+    pixels: Vec<Rgb565>,
+}
+
+impl MemoryDisplay {
+    pub fn new(width: u32, height: u32) -> Self {
+        MemoryDisplay {
+            size: Size::new(width, height),
+            pixels: alloc::vec![Rgb565::BLACK; (width * height) as usize],
+        }
+    }
+
+    fn get_index(&self, pos: Point) -> Option<usize> {
+        if pos.x < 0 || pos.y < 0 || pos.x as u32 >= self.size.width || pos.y as u32 >= self.size.height {
+            return None;
+        }
+        Some(pos.y as usize * self.size.width as usize + pos.x as usize)
+    }
+
+    /// None outside of the display
+    pub fn get_pixel(&self, pos: Point) -> Option<Rgb565> {
+        self.get_index(pos).map(|index| self.pixels[index])
+    }
+
+    /// Row major
+    pub fn get_pixels(&self) -> &[Rgb565] {
+        &self.pixels
+    }
+
+    pub fn count_pixels(&self, color: Rgb565) -> usize {
+        self.pixels.iter().filter(|p| **p == color).count()
+    }
+}
+
+impl OriginDimensions for MemoryDisplay {
+    fn size(&self) -> Size {
+        self.size
+    }
+}
+
+impl DrawTarget for MemoryDisplay {
+    type Color = Rgb565;
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+        where I: IntoIterator<Item=Pixel<Self::Color>> {
+        for Pixel(pos, color) in pixels {
+            if let Some(index) = self.get_index(pos) {
+                self.pixels[index] = color;
+            }
+        }
+        Ok(())
+    }
+
+    fn clear(&mut self, color: Self::Color) -> Result<(), Self::Error> {
+        self.pixels.iter_mut().for_each(|p| *p = color);
+        Ok(())
+    }
+}
@@ -0,0 +1,492 @@
+//! I2C bus mocks for driver tests on a host, usable with the async and the blocking drivers.
+//!
+//! ScriptedI2c checks the exact operations against a list of expectations, ModelI2c answers
+//! from a simulated register map like Ft6236Model or Tsc2007Model. MockPin and NoDelay stand
+//! in for the INT, PENIRQ and reset lines and the delay of the drivers.
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use core::future::Future;
+use core::pin::pin;
+use core::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+
+use embedded_hal::i2c::{ErrorKind, ErrorType, NoAcknowledgeSource, Operation};
+
+use crate::ft6236_asynch::{FT6236_DEFAULT_ADDR, Gesture, PointEvent, regs};
+use crate::tsc2007::{TSC2007_ADC_8BIT, TSC2007_ADDR, TSC2007_MEASURE_X, TSC2007_MEASURE_Y, TSC2007_MEASURE_Z1,
+                     TSC2007_MEASURE_Z2, TSC2007_POWERDOWN_IRQON};
+
+fn noop_raw_waker() -> RawWaker {
+    fn clone(_: *const ()) -> RawWaker {
+        noop_raw_waker()
+    }
+    fn noop(_: *const ()) {}
+    static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, noop, noop, noop);
+    RawWaker::new(core::ptr::null(), &VTABLE)
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct MockPinError;
+
+impl embedded_hal::digital::Error for MockPinError {
+    fn kind(&self) -> embedded_hal::digital::ErrorKind {
+        embedded_hal::digital::ErrorKind::Other
+    }
+}
+
+/// Output and interrupt line, every wait returns at once. After fail_after waits
+/// the next wait fails, e.g. to end a driver loop
+pub struct MockPin {
+    fail_after: Option<usize>,
+    wait_for_low_count: usize,
+    wait_for_falling_edge_count: usize,
+    is_high: bool,
+    set_low_count: usize,
+}
+
+impl MockPin {
+    pub fn new() -> Self {
+        MockPin {
+            fail_after: None,
+            wait_for_low_count: 0,
+            wait_for_falling_edge_count: 0,
+            is_high: true,
+            set_low_count: 0,
+        }
+    }
+
+    pub fn fail_after(waits: usize) -> Self {
+        MockPin {
+            fail_after: Some(waits),
+            ..MockPin::new()
+        }
+    }
+
+    pub fn get_wait_for_low_count(&self) -> usize {
+        self.wait_for_low_count
+    }
+
+    pub fn get_wait_for_falling_edge_count(&self) -> usize {
+        self.wait_for_falling_edge_count
+    }
+
+    pub fn is_high(&self) -> bool {
+        self.is_high
+    }
+
+    pub fn get_set_low_count(&self) -> usize {
+        self.set_low_count
+    }
+
+    fn wait(&mut self) -> Result<(), MockPinError> {
+        match self.fail_after {
+            Some(0) => Err(MockPinError),
+            Some(waits) => {
+                self.fail_after = Some(waits - 1);
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+impl Default for MockPin {
+    fn default() -> Self {
+        MockPin::new()
+    }
+}
+
+impl embedded_hal::digital::ErrorType for MockPin {
+    type Error = MockPinError;
+}
+
+impl embedded_hal::digital::OutputPin for MockPin {
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        self.is_high = false;
+        self.set_low_count += 1;
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        self.is_high = true;
+        Ok(())
+    }
+}
+
+impl embedded_hal_async::digital::Wait for MockPin {
+    async fn wait_for_high(&mut self) -> Result<(), Self::Error> {
+        self.wait()
+    }
+
+    async fn wait_for_low(&mut self) -> Result<(), Self::Error> {
+        self.wait_for_low_count += 1;
+        self.wait()
+    }
+
+    async fn wait_for_rising_edge(&mut self) -> Result<(), Self::Error> {
+        self.wait()
+    }
+
+    async fn wait_for_falling_edge(&mut self) -> Result<(), Self::Error> {
+        self.wait_for_falling_edge_count += 1;
+        self.wait()
+    }
+
+    async fn wait_for_any_edge(&mut self) -> Result<(), Self::Error> {
+        self.wait()
+    }
+}
+
+/// Returns at once and counts the delays
+#[derive(Default)]
+pub struct NoDelay {
+    pub delay_count: usize,
+}
+
+impl embedded_hal::delay::DelayNs for NoDelay {
+    fn delay_ns(&mut self, _ns: u32) {
+        self.delay_count += 1;
+    }
+}
+
+impl embedded_hal_async::delay::DelayNs for NoDelay {
+    async fn delay_ns(&mut self, _ns: u32) {
+        self.delay_count += 1;
+    }
+}
+
+/// Polls the future until it is ready, for the async drivers on a mock bus that never blocks
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    let waker = unsafe { Waker::from_raw(noop_raw_waker()) };
+    let mut context = Context::from_waker(&waker);
+    let mut future = pin!(future);
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut context) {
+            return output;
+        }
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum MockI2cError {
+    /// no device answers on the address
+    NoAcknowledge,
+    /// injected with fail_next
+    Bus,
+}
+
+impl embedded_hal::i2c::Error for MockI2cError {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            MockI2cError::NoAcknowledge => ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address),
+            MockI2cError::Bus => ErrorKind::Bus,
+        }
+    }
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum Transaction {
+    /// address and the expected bytes
+    Write(u8, Vec<u8>),
+    /// address and the bytes returned
+    Read(u8, Vec<u8>),
+}
+
+/// Panics on the first operation that does not match the next expectation
+pub struct ScriptedI2c {
+    expectations: Vec<Transaction>,
+    index: usize,
+}
+
+impl ScriptedI2c {
+    pub fn new(expectations: &[Transaction]) -> Self {
+        ScriptedI2c {
+            expectations: expectations.to_vec(),
+            index: 0,
+        }
+    }
+
+    /// Panics if not all expectations were used
+    pub fn done(&self) {
+        assert_eq!(self.index, self.expectations.len(), "not all expected I2C transactions were done");
+    }
+
+    fn next_expectation(&mut self) -> Transaction {
+        let expectation = self.expectations.get(self.index)
+            .unwrap_or_else(|| panic!("unexpected I2C transaction {}", self.index))
+            .clone();
+        self.index += 1;
+        expectation
+    }
+
+    fn run(&mut self, address: u8, operations: &mut [Operation<'_>]) -> Result<(), MockI2cError> {
+        for operation in operations.iter_mut() {
+            match (operation, self.next_expectation()) {
+                (Operation::Write(data), Transaction::Write(expected_address, expected)) => {
+                    assert_eq!(address, expected_address, "I2C write to wrong address");
+                    assert_eq!(*data, expected.as_slice(), "unexpected I2C write data");
+                }
+                (Operation::Read(buffer), Transaction::Read(expected_address, response)) => {
+                    assert_eq!(address, expected_address, "I2C read from wrong address");
+                    assert_eq!(buffer.len(), response.len(), "unexpected I2C read length");
+                    buffer.copy_from_slice(&response);
+                }
+                (operation, expectation) => {
+                    panic!("expected {:?}, got {}", expectation,
+                           if matches!(operation, Operation::Write(_)) { "write" } else { "read" });
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ErrorType for ScriptedI2c {
+    type Error = MockI2cError;
+}
+
+impl embedded_hal::i2c::I2c for ScriptedI2c {
+    fn transaction(&mut self, address: u8, operations: &mut [Operation<'_>]) -> Result<(), Self::Error> {
+        self.run(address, operations)
+    }
+}
+
+impl embedded_hal_async::i2c::I2c for ScriptedI2c {
+    async fn transaction(&mut self, address: u8, operations: &mut [Operation<'_>]) -> Result<(), Self::Error> {
+        self.run(address, operations)
+    }
+}
+
+/// Simulated device behind ModelI2c
+pub trait RegisterModel {
+    fn write(&mut self, data: &[u8]);
+
+    fn read(&mut self, buffer: &mut [u8]);
+}
+
+/// Bus with one simulated device, other addresses are not acknowledged
+pub struct ModelI2c<M> {
+    addr: u8,
+    model: M,
+    fail_next: bool,
+    transaction_count: usize,
+}
+
+impl<M: RegisterModel> ModelI2c<M> {
+    pub fn new(addr: u8, model: M) -> Self {
+        ModelI2c {
+            addr,
+            model,
+            fail_next: false,
+            transaction_count: 0,
+        }
+    }
+
+    pub fn model(&mut self) -> &mut M {
+        &mut self.model
+    }
+
+    /// The next transaction fails with MockI2cError::Bus
+    pub fn fail_next(&mut self) {
+        self.fail_next = true;
+    }
+
+    pub fn get_transaction_count(&self) -> usize {
+        self.transaction_count
+    }
+
+    fn run(&mut self, address: u8, operations: &mut [Operation<'_>]) -> Result<(), MockI2cError> {
+        self.transaction_count += 1;
+        if self.fail_next {
+            self.fail_next = false;
+            return Err(MockI2cError::Bus);
+        }
+        if address != self.addr {
+            return Err(MockI2cError::NoAcknowledge);
+        }
+        for operation in operations.iter_mut() {
+            match operation {
+                Operation::Write(data) => self.model.write(data),
+                Operation::Read(buffer) => self.model.read(buffer),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<M> ErrorType for ModelI2c<M> {
+    type Error = MockI2cError;
+}
+
+impl<M: RegisterModel> embedded_hal::i2c::I2c for ModelI2c<M> {
+    fn transaction(&mut self, address: u8, operations: &mut [Operation<'_>]) -> Result<(), Self::Error> {
+        self.run(address, operations)
+    }
+}
+
+impl<M: RegisterModel> embedded_hal_async::i2c::I2c for ModelI2c<M> {
+    async fn transaction(&mut self, address: u8, operations: &mut [Operation<'_>]) -> Result<(), Self::Error> {
+        self.run(address, operations)
+    }
+}
+
+/// FT6236 register map, the first written byte selects the register,
+/// reads and further writes auto increment
+pub struct Ft6236Model {
+    registers: [u8; 256],
+    pointer: u8,
+}
+
+impl Ft6236Model {
+    pub fn new(chip_id: u8) -> Self {
+        let mut registers = [0u8; 256];
+        registers[regs::CHIPID as usize] = chip_id;
+        registers[regs::VENDID as usize] = 0x11;
+        registers[regs::FIRMVERS as usize] = 0x10;
+        let mut model = Ft6236Model {
+            registers,
+            pointer: 0,
+        };
+        model.clear_touches();
+        model
+    }
+
+    pub fn new_bus(chip_id: u8) -> ModelI2c<Self> {
+        ModelI2c::new(FT6236_DEFAULT_ADDR, Ft6236Model::new(chip_id))
+    }
+
+    pub fn get_register(&self, reg_addr: u8) -> u8 {
+        self.registers[reg_addr as usize]
+    }
+
+    pub fn set_register(&mut self, reg_addr: u8, value: u8) {
+        self.registers[reg_addr as usize] = value;
+    }
+
+    /// Sets touch point nth, 0 or 1, number_of_touches is not changed.
+    /// A touch_id of 0x0F is reported as invalid by the driver
+    pub fn set_touch(&mut self, nth: usize, point: PointEvent) {
+        let start = 0x03 + 6 * nth;
+        self.registers[start] = ((point.event as u8) << 6) | ((point.x >> 8) as u8 & 0b111);
+        self.registers[start + 1] = point.x as u8;
+        self.registers[start + 2] = (point.touch_id << 4) | ((point.y >> 8) as u8 & 0b111);
+        self.registers[start + 3] = point.y as u8;
+        self.registers[start + 4] = point.weight;
+        self.registers[start + 5] = point.area & 0b1111;
+    }
+
+    pub fn set_number_of_touches(&mut self, number_of_touches: u8) {
+        self.registers[regs::NUMTOUCHES as usize] = number_of_touches;
+    }
+
+    /// Touch points with the invalid touch id 0x0F and no touches
+    pub fn clear_touches(&mut self) {
+        for nth in 0..2 {
+            self.registers[0x03 + 6 * nth..0x09 + 6 * nth].copy_from_slice(&[0xFF; 6]);
+        }
+        self.registers[regs::NUMTOUCHES as usize] = 0;
+        self.registers[regs::GEST_ID as usize] = 0;
+    }
+
+    pub fn set_gesture(&mut self, gesture: Option<Gesture>) {
+        self.registers[regs::GEST_ID as usize] = gesture.map_or(0, |g| g.gesture_id());
+    }
+}
+
+impl RegisterModel for Ft6236Model {
+    fn write(&mut self, data: &[u8]) {
+        if let Some((pointer, values)) = data.split_first() {
+            self.pointer = *pointer;
+            for value in values {
+                self.registers[self.pointer as usize] = *value;
+                self.pointer = self.pointer.wrapping_add(1);
+            }
+        }
+    }
+
+    fn read(&mut self, buffer: &mut [u8]) {
+        for value in buffer.iter_mut() {
+            *value = self.registers[self.pointer as usize];
+            self.pointer = self.pointer.wrapping_add(1);
+        }
+    }
+}
+
+/// TSC2007 with fixed conversion results per function, values are 12 bit
+pub struct Tsc2007Model {
+    values: [u16; 16],
+    command: u8,
+    conversion_count: usize,
+}
+
+impl Tsc2007Model {
+    pub fn new() -> Self {
+        Tsc2007Model {
+            values: [0u16; 16],
+            command: 0,
+            conversion_count: 0,
+        }
+    }
+
+    pub fn new_bus() -> ModelI2c<Self> {
+        ModelI2c::new(TSC2007_ADDR, Tsc2007Model::new())
+    }
+
+    /// Conversion result of a function, e.g. TSC2007_MEASURE_AUX
+    pub fn set_value(&mut self, function: u8, value: u16) {
+        self.values[(function & 0x0F) as usize] = value & 0xFFF;
+    }
+
+    /// X, Y, Z1 and Z2 results
+    pub fn set_touch(&mut self, x: u16, y: u16, z1: u16, z2: u16) {
+        for (function, value) in [(TSC2007_MEASURE_X, x), (TSC2007_MEASURE_Y, y), (TSC2007_MEASURE_Z1, z1), (TSC2007_MEASURE_Z2, z2)] {
+            self.set_value(function, value);
+        }
+    }
+
+    /// Z1 0 and Z2 full scale, no touch for any pressure threshold
+    pub fn release(&mut self) {
+        self.set_touch(0, 0, 0, 0xFFF);
+    }
+
+    pub fn get_last_command(&self) -> u8 {
+        self.command
+    }
+
+    /// PENIRQ is enabled after the last command
+    pub fn is_irq_enabled(&self) -> bool {
+        (self.command >> 2) & 0x03 == TSC2007_POWERDOWN_IRQON
+    }
+
+    pub fn get_conversion_count(&self) -> usize {
+        self.conversion_count
+    }
+}
+
+impl Default for Tsc2007Model {
+    fn default() -> Self {
+        Tsc2007Model::new()
+    }
+}
+
+impl RegisterModel for Tsc2007Model {
+    fn write(&mut self, data: &[u8]) {
+        if let Some(command) = data.first() {
+            self.command = *command;
+            self.conversion_count += 1;
+        }
+    }
+
+    fn read(&mut self, buffer: &mut [u8]) {
+        let value = self.values[(self.command >> 4) as usize];
+        let result = if (self.command >> 1) & 0x01 == TSC2007_ADC_8BIT {
+            [(value >> 4) as u8, 0]
+        } else {
+            [(value >> 4) as u8, ((value & 0x0F) << 4) as u8]
+        };
+        for (byte, value) in buffer.iter_mut().zip(result) {
+            *byte = value;
+        }
+    }
+}
@@ -0,0 +1,342 @@
+extern crate alloc;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use embedded_graphics::draw_target::DrawTarget;
+use embedded_graphics::Drawable;
+use embedded_graphics::geometry::{Point, Size};
+use embedded_graphics::mono_font::MonoTextStyle;
+use embedded_graphics::pixelcolor::Rgb565;
+use embedded_graphics::primitives::Rectangle;
+use embedded_graphics::text::{Alignment, Baseline, Text, TextStyle, TextStyleBuilder};
+
+use crate::ft6236_asynch::Gesture;
+use crate::graphics::{GraphicUtils, Theme};
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum SwipeDirection {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+impl SwipeDirection {
+    pub fn from_gesture(gesture: Gesture) -> Option<Self> {
+        match gesture {
+            Gesture::MoveLeft => Some(SwipeDirection::Left),
+            Gesture::MoveRight => Some(SwipeDirection::Right),
+            Gesture::MoveUp => Some(SwipeDirection::Up),
+            Gesture::MoveDown => Some(SwipeDirection::Down),
+            _ => None,
+        }
+    }
+
+    /// Direction of the dominant axis of delta, None if it is shorter than min_distance
+    pub fn from_delta(delta: Point, min_distance: u32) -> Option<Self> {
+        let min_distance = min_distance as i32;
+        if delta.x.abs() >= delta.y.abs() && delta.x.abs() >= min_distance {
+            Some(if delta.x < 0 { SwipeDirection::Left } else { SwipeDirection::Right })
+        } else if delta.y.abs() > delta.x.abs() && delta.y.abs() >= min_distance {
+            Some(if delta.y < 0 { SwipeDirection::Up } else { SwipeDirection::Down })
+        } else {
+            None
+        }
+    }
+}
+
+/// Software swipe detection for panels without hardware gestures, feed it
+/// with the mapped touch position or None if the panel is not touched
+pub struct SwipeDetector {
+    min_distance: u32,
+    max_duration_ms: u64,
+    start: Option<(Point, u64)>,
+    last_pos: Point,
+}
+
+impl SwipeDetector {
+    pub fn new(min_distance: u32, max_duration_ms: u64) -> Self {
+        SwipeDetector {
+            min_distance,
+            max_duration_ms,
+            start: None,
+            last_pos: Point::zero(),
+        }
+    }
+
+    pub fn update(&mut self, pos: Option<Point>, now_ms: u64) -> Option<SwipeDirection> {
+        match pos {
+            Some(pos) => {
+                if self.start.is_none() {
+                    self.start = Some((pos, now_ms));
+                }
+                self.last_pos = pos;
+                None
+            }
+            None => {
+                let (start_pos, start_ms) = self.start.take()?;
+                if now_ms.saturating_sub(start_ms) > self.max_duration_ms {
+                    return None;
+                }
+                SwipeDirection::from_delta(self.last_pos - start_pos, self.min_distance)
+            }
+        }
+    }
+}
+
+impl Default for SwipeDetector {
+    fn default() -> Self {
+        SwipeDetector::new(50, 500)
+    }
+}
+
+pub struct TabBar<'a> {
+    tabs: Vec<String>,
+    pos: Point,
+    size: Size,
+    selected_index: usize,
+    highlight_color: Rgb565,
+    background_color: Rgb565,
+    character_style: MonoTextStyle<'a, Rgb565>,
+}
+
+impl<'a> TabBar<'a> {
+    pub fn new(tabs: &[&str], position: Point, size: Size,
+               character_style: MonoTextStyle<'a, Rgb565>, theme: &Theme) -> Self {
+        let mut tab_character_style = character_style;
+        tab_character_style.text_color = Some(theme.text_color_primary);
+        TabBar {
+            tabs: tabs.iter().map(|t| String::from(*t)).collect(),
+            pos: position,
+            size,
+            selected_index: 0,
+            highlight_color: theme.highlight_color,
+            background_color: theme.screen_background_color,
+            character_style: tab_character_style,
+        }
+    }
+
+    fn get_text_style(&self) -> TextStyle {
+        TextStyleBuilder::new()
+            .alignment(Alignment::Center)
+            .baseline(Baseline::Middle)
+            .build()
+    }
+
+    fn get_tab_bounding_box(&self, index: usize) -> Rectangle {
+        let tab_width = self.size.width / self.tabs.len().max(1) as u32;
+        Rectangle::new(Point::new(self.pos.x + (index as u32 * tab_width) as i32, self.pos.y),
+                       Size::new(tab_width, self.size.height))
+    }
+
+    pub fn get_bounding_box(&self) -> Rectangle {
+        Rectangle::new(self.pos, self.size)
+    }
+
+    pub fn draw<D>(&self, display: &mut D) -> Result<(), D::Error>
+        where D: DrawTarget<Color=Rgb565> {
+        for index in 0..self.tabs.len() {
+            self.draw_tab(display, index)?;
+        }
+        Ok(())
+    }
+
+    fn draw_tab<D>(&self, display: &mut D, index: usize) -> Result<(), D::Error>
+        where D: DrawTarget<Color=Rgb565> {
+        let bounding_box = self.get_tab_bounding_box(index);
+        let color = if index == self.selected_index { self.highlight_color } else { self.background_color };
+        display.fill_solid(&bounding_box, color)?;
+        if let Some(text) = GraphicUtils::get_text_with_ellipsis_checked(bounding_box.size.width, self.tabs[index].as_str(),
+                                                                         self.character_style.font) {
+            Text::with_text_style(text.as_str(), bounding_box.center(), self.character_style, self.get_text_style())
+                .draw(display)?;
+        }
+        Ok(())
+    }
+
+    pub fn get_selected_index(&self) -> usize {
+        self.selected_index
+    }
+
+    pub fn set_selected_index<D>(&mut self, display: &mut D, index: usize) -> Result<(), D::Error>
+        where D: DrawTarget<Color=Rgb565> {
+        if index >= self.tabs.len() || index == self.selected_index {
+            return Ok(());
+        }
+        let previous_index = self.selected_index;
+        self.selected_index = index;
+        self.draw_tab(display, previous_index)?;
+        self.draw_tab(display, index)
+    }
+
+    /// Returns the newly selected tab if pos hit a tab
+    pub fn select_at_pos<D>(&mut self, display: &mut D, pos: Point) -> Result<Option<usize>, D::Error>
+        where D: DrawTarget<Color=Rgb565> {
+        for index in 0..self.tabs.len() {
+            if self.get_tab_bounding_box(index).contains(pos) {
+                self.set_selected_index(display, index)?;
+                return Ok(Some(index));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Swipe left selects the next tab, swipe right the previous one
+    pub fn handle_swipe<D>(&mut self, display: &mut D, direction: SwipeDirection) -> Result<Option<usize>, D::Error>
+        where D: DrawTarget<Color=Rgb565> {
+        let index = match direction {
+            SwipeDirection::Left if self.selected_index + 1 < self.tabs.len() => self.selected_index + 1,
+            SwipeDirection::Right if self.selected_index > 0 => self.selected_index - 1,
+            _ => return Ok(None),
+        };
+        self.set_selected_index(display, index)?;
+        Ok(Some(index))
+    }
+}
+
+/// Horizontally paged screens, swipe left shows the next page
+pub struct Pager {
+    page_count: usize,
+    current_page: usize,
+    wrap_around: bool,
+}
+
+impl Pager {
+    pub fn new(page_count: usize, wrap_around: bool) -> Self {
+        Pager {
+            page_count,
+            current_page: 0,
+            wrap_around,
+        }
+    }
+
+    pub fn get_current_page(&self) -> usize {
+        self.current_page
+    }
+
+    pub fn get_page_count(&self) -> usize {
+        self.page_count
+    }
+
+    pub fn set_current_page(&mut self, page: usize) {
+        if page < self.page_count {
+            self.current_page = page;
+        }
+    }
+
+    /// Returns the new page if the swipe changed the page
+    pub fn handle_swipe(&mut self, direction: SwipeDirection) -> Option<usize> {
+        let page = match direction {
+            SwipeDirection::Left if self.current_page + 1 < self.page_count => self.current_page + 1,
+            SwipeDirection::Left if self.wrap_around && self.page_count > 1 => 0,
+            SwipeDirection::Right if self.current_page > 0 => self.current_page - 1,
+            SwipeDirection::Right if self.wrap_around && self.page_count > 1 => self.page_count - 1,
+            _ => return None,
+        };
+        self.current_page = page;
+        Some(page)
+    }
+}
+
+/// Stack of screens, the root page can not be popped
+pub struct PageStack<P> {
+    pages: Vec<P>,
+}
+
+impl<P: Copy + PartialEq> PageStack<P> {
+    pub fn new(root_page: P) -> Self {
+        PageStack {
+            pages: alloc::vec![root_page],
+        }
+    }
+
+    pub fn push(&mut self, page: P) {
+        self.pages.push(page);
+    }
+
+    /// Returns the page that is visible after pop
+    pub fn pop(&mut self) -> P {
+        if self.pages.len() > 1 {
+            self.pages.pop();
+        }
+        self.get_current_page()
+    }
+
+    /// Pops until page is on top, returns false if page is not on the stack
+    pub fn pop_to(&mut self, page: P) -> bool {
+        match self.pages.iter().rposition(|p| *p == page) {
+            Some(index) => {
+                self.pages.truncate(index + 1);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn get_current_page(&self) -> P {
+        self.pages[self.pages.len() - 1]
+    }
+
+    /// Swipe right navigates back, returns the new page if one was popped
+    pub fn handle_swipe(&mut self, direction: SwipeDirection) -> Option<P> {
+        if direction == SwipeDirection::Right && !self.is_root() {
+            return Some(self.pop());
+        }
+        None
+    }
+
+    pub fn get_depth(&self) -> usize {
+        self.pages.len()
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.pages.len() == 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embedded_graphics::primitives::PointsIter;
+    use profont::PROFONT_12_POINT;
+
+    use crate::memory_display::MemoryDisplay;
+
+    use super::*;
+
+    fn count_pixels_in(display: &MemoryDisplay, area: Rectangle, color: Rgb565) -> usize {
+        area.points().filter(|pos| display.get_pixel(*pos) == Some(color)).count()
+    }
+
+    #[test]
+    fn tabs_draw_labels_and_move_the_highlight() {
+        let mut display = MemoryDisplay::new(240, 32);
+        let style = MonoTextStyle::new(&PROFONT_12_POINT, Rgb565::new(31, 63, 31));
+        let theme = Theme::new_dark_theme();
+        let mut tab_bar = TabBar::new(&["Home", "Wifi", "About"], Point::zero(), Size::new(240, 24), style, &theme);
+        tab_bar.draw(&mut display).unwrap();
+        for index in 0..3 {
+            assert!(count_pixels_in(&display, tab_bar.get_tab_bounding_box(index), theme.text_color_primary) > 0);
+        }
+        assert!(count_pixels_in(&display, tab_bar.get_tab_bounding_box(0), theme.highlight_color) > 0);
+        assert_eq!(count_pixels_in(&display, tab_bar.get_tab_bounding_box(1), theme.highlight_color), 0);
+
+        assert_eq!(tab_bar.handle_swipe(&mut display, SwipeDirection::Left).unwrap(), Some(1));
+        assert_eq!(count_pixels_in(&display, tab_bar.get_tab_bounding_box(0), theme.highlight_color), 0);
+        assert!(count_pixels_in(&display, tab_bar.get_tab_bounding_box(1), theme.highlight_color) > 0);
+        assert!(count_pixels_in(&display, tab_bar.get_tab_bounding_box(1), theme.text_color_primary) > 0);
+        assert_eq!(tab_bar.select_at_pos(&mut display, Point::new(200, 30)).unwrap(), None);
+    }
+
+    #[test]
+    fn narrow_tabs_draw_without_labels() {
+        let mut display = MemoryDisplay::new(64, 32);
+        let style = MonoTextStyle::new(&PROFONT_12_POINT, Rgb565::new(31, 63, 31));
+        let theme = Theme::new_dark_theme();
+        let mut tab_bar = TabBar::new(&["Home", "Settings", "Network", "About"], Point::zero(), Size::new(40, 20),
+                                      style, &theme);
+        tab_bar.draw(&mut display).unwrap();
+        assert_eq!(display.count_pixels(theme.text_color_primary), 0);
+        assert_eq!(tab_bar.select_at_pos(&mut display, Point::new(35, 5)).unwrap(), Some(3));
+    }
+}
@@ -0,0 +1,341 @@
+//! Sampling, filtering and pen state handling shared by the resistive touch drivers
+
+use embedded_hal_async::delay::DelayNs;
+use embedded_hal_async::digital::Wait;
+
+pub const MAX_OVERSAMPLING: usize = 16;
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum AdcResolution {
+    Bits8,
+    Bits12,
+}
+
+impl AdcResolution {
+    pub fn max_value(&self) -> u16 {
+        match self {
+            AdcResolution::Bits8 => 0xFF,
+            AdcResolution::Bits12 => 0xFFF,
+        }
+    }
+    /// Scales a 12 bit threshold to this resolution
+    pub fn from_12bit(&self, value: u16) -> u16 {
+        match self {
+            AdcResolution::Bits8 => value >> 4,
+            AdcResolution::Bits12 => value,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum SampleFilter {
+    Average,
+    Median,
+    /// average of the samples without the lowest and highest quarter
+    TrimmedAverage,
+}
+
+impl SampleFilter {
+    /// samples is reordered
+    pub fn apply(&self, samples: &mut [u16]) -> u16 {
+        if samples.is_empty() {
+            return 0;
+        }
+        match self {
+            SampleFilter::Average => average(samples),
+            SampleFilter::Median => {
+                samples.sort_unstable();
+                samples[samples.len() / 2]
+            }
+            SampleFilter::TrimmedAverage => {
+                samples.sort_unstable();
+                let trim = samples.len() / 4;
+                average(&samples[trim..samples.len() - trim])
+            }
+        }
+    }
+}
+
+fn average(samples: &[u16]) -> u16 {
+    (samples.iter().map(|s| *s as u32).sum::<u32>() / samples.len() as u32) as u16
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct ResistiveTouchConfig {
+    pub resolution: AdcResolution,
+    /// conversions per channel, 1..=MAX_OVERSAMPLING
+    pub oversampling: u8,
+    pub filter: SampleFilter,
+    /// X plate resistance in ohms, from the panel datasheet
+    pub x_plate_resistance: u16,
+    /// Z1 at 12 bit resolution below this is no touch
+    pub min_z1: u16,
+    /// touch resistance in ohms above this is no touch, a lower resistance means more pressure
+    pub max_touch_resistance: u32,
+    /// consecutive samples before pen down or pen up is reported
+    pub debounce_samples: u8,
+}
+
+impl ResistiveTouchConfig {
+    pub fn get_oversampling(&self) -> usize {
+        (self.oversampling as usize).clamp(1, MAX_OVERSAMPLING)
+    }
+}
+
+impl Default for ResistiveTouchConfig {
+    fn default() -> Self {
+        ResistiveTouchConfig {
+            resolution: AdcResolution::Bits12,
+            oversampling: 1,
+            filter: SampleFilter::Median,
+            x_plate_resistance: 300,
+            min_z1: 100,
+            max_touch_resistance: 3000,
+            debounce_samples: 1,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct ResistiveSample {
+    pub x: u16,
+    pub y: u16,
+    pub z1: u16,
+    pub z2: u16,
+    /// touch resistance in ohms, None if z1 is 0
+    pub resistance: Option<u32>,
+}
+
+impl ResistiveSample {
+    pub fn new(x: u16, y: u16, z1: u16, z2: u16, config: &ResistiveTouchConfig) -> Self {
+        ResistiveSample {
+            x,
+            y,
+            z1,
+            z2,
+            resistance: touch_resistance(x, z1, z2, config.x_plate_resistance, config.resolution),
+        }
+    }
+
+    pub fn is_touched(&self, config: &ResistiveTouchConfig) -> bool {
+        let min_z1 = config.resolution.from_12bit(config.min_z1);
+        match self.resistance {
+            Some(resistance) => self.z1 > min_z1 && resistance <= config.max_touch_resistance,
+            None => false,
+        }
+    }
+}
+
+/// Rtouch = Rx-plate * X / full scale * (Z2 / Z1 - 1)
+pub fn touch_resistance(x: u16, z1: u16, z2: u16, x_plate_resistance: u16, resolution: AdcResolution) -> Option<u32> {
+    if z1 == 0 {
+        return None;
+    }
+    let full_scale = resolution.max_value() as u64 + 1;
+    let resistance = x_plate_resistance as u64 * x as u64 * z2.saturating_sub(z1) as u64 / (full_scale * z1 as u64);
+    Some(resistance.min(u32::MAX as u64) as u32)
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum PenEvent {
+    Down(ResistiveSample),
+    Move(ResistiveSample),
+    /// last sample before the pen was lifted
+    Up(ResistiveSample),
+}
+
+/// Debounced pen down and pen up detection
+#[derive(Copy, Clone, Debug)]
+pub struct PenStateMachine {
+    pen_down: bool,
+    count: u8,
+    last_sample: Option<ResistiveSample>,
+}
+
+impl PenStateMachine {
+    pub fn new() -> Self {
+        PenStateMachine {
+            pen_down: false,
+            count: 0,
+            last_sample: None,
+        }
+    }
+
+    pub fn is_pen_down(&self) -> bool {
+        self.pen_down
+    }
+
+    pub fn update(&mut self, sample: ResistiveSample, config: &ResistiveTouchConfig) -> Option<PenEvent> {
+        let touched = sample.is_touched(config);
+        if touched == self.pen_down {
+            self.count = 0;
+            if touched {
+                self.last_sample = Some(sample);
+                return Some(PenEvent::Move(sample));
+            }
+            return None;
+        }
+        self.count += 1;
+        if self.count < config.debounce_samples.max(1) {
+            return None;
+        }
+        self.count = 0;
+        self.pen_down = touched;
+        if touched {
+            self.last_sample = Some(sample);
+            Some(PenEvent::Down(sample))
+        } else {
+            self.last_sample.take().map(PenEvent::Up)
+        }
+    }
+}
+
+impl Default for PenStateMachine {
+    fn default() -> Self {
+        PenStateMachine::new()
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum PenIrqError<BusError, PinError> {
+    /// I2C or SPI error of the driver
+    Bus(BusError),
+    Pin(PinError),
+}
+
+/// Conversions used by wait_for_touch, implemented by the async resistive touch drivers
+pub(crate) trait PenIrqDriver {
+    type Error;
+
+    /// Powers down the ADC and enables PENIRQ
+    async fn init_irq(&mut self) -> Result<u16, Self::Error>;
+
+    async fn poll(&mut self) -> Result<Option<PenEvent>, Self::Error>;
+
+    fn is_pen_down(&self) -> bool;
+
+    fn get_config(&self) -> ResistiveTouchConfig;
+}
+
+/// Waits for pen down on PENIRQ and then samples every interval_ms until pen up,
+/// every pen event is passed to on_event. Returns after the pen up event
+pub(crate) async fn wait_for_touch<T, P, D, F>(driver: &mut T, penirq: &mut P, delay: &mut D, interval_ms: u32,
+                                              mut on_event: F) -> Result<(), PenIrqError<T::Error, P::Error>>
+    where T: PenIrqDriver, P: Wait, D: DelayNs, F: FnMut(PenEvent) {
+    loop {
+        driver.init_irq().await.map_err(PenIrqError::Bus)?;
+        penirq.wait_for_low().await.map_err(PenIrqError::Pin)?;
+
+        let mut idle_samples = 0;
+        loop {
+            match driver.poll().await.map_err(PenIrqError::Bus)? {
+                Some(event) => {
+                    idle_samples = 0;
+                    on_event(event);
+                    if let PenEvent::Up(_) = event {
+                        return Ok(());
+                    }
+                }
+                // while the pen is down the state machine is debouncing the pen up,
+                // keep sampling until it reports Up
+                None if driver.is_pen_down() => {}
+                None => {
+                    // PENIRQ without a valid touch, go back to waiting
+                    idle_samples += 1;
+                    if idle_samples > driver.get_config().debounce_samples {
+                        break;
+                    }
+                }
+            }
+            delay.delay_ms(interval_ms).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate alloc;
+
+    use alloc::vec::Vec;
+
+    use crate::mock_i2c::{block_on, MockPin, MockPinError, NoDelay};
+
+    use super::*;
+
+    /// Runs the pen state machine over scripted touched and released samples,
+    /// released after the end of the script
+    struct ScriptedDriver {
+        touched: Vec<bool>,
+        index: usize,
+        config: ResistiveTouchConfig,
+        pen: PenStateMachine,
+    }
+
+    impl ScriptedDriver {
+        fn new(touched: &[bool], debounce_samples: u8) -> Self {
+            ScriptedDriver {
+                touched: touched.to_vec(),
+                index: 0,
+                config: ResistiveTouchConfig { debounce_samples, ..ResistiveTouchConfig::default() },
+                pen: PenStateMachine::new(),
+            }
+        }
+    }
+
+    impl PenIrqDriver for ScriptedDriver {
+        type Error = ();
+
+        async fn init_irq(&mut self) -> Result<u16, Self::Error> {
+            Ok(0)
+        }
+
+        async fn poll(&mut self) -> Result<Option<PenEvent>, Self::Error> {
+            let touched = self.touched.get(self.index).copied().unwrap_or(false);
+            self.index += 1;
+            let sample = if touched {
+                ResistiveSample::new(2000, 1500, 400, 800, &self.config)
+            } else {
+                ResistiveSample::new(0, 0, 0, 0xFFF, &self.config)
+            };
+            Ok(self.pen.update(sample, &self.config))
+        }
+
+        fn is_pen_down(&self) -> bool {
+            self.pen.is_pen_down()
+        }
+
+        fn get_config(&self) -> ResistiveTouchConfig {
+            self.config
+        }
+    }
+
+    #[test]
+    fn long_press_with_release_glitches_ends_with_up() {
+        let mut script = alloc::vec![true, true];
+        for _ in 0..5 {
+            script.extend_from_slice(&[false, true]);
+        }
+        let mut driver = ScriptedDriver::new(&script, 2);
+        let mut penirq = MockPin::fail_after(1);
+        let mut events = Vec::new();
+
+        let result = block_on(wait_for_touch(&mut driver, &mut penirq, &mut NoDelay::default(), 10, |e| events.push(e)));
+        assert_eq!(result, Ok(()));
+        assert_eq!(penirq.get_wait_for_low_count(), 1);
+        assert!(matches!(events.first(), Some(PenEvent::Down(_))));
+        assert_eq!(events.iter().filter(|e| matches!(e, PenEvent::Move(_))).count(), 5);
+        assert!(matches!(events.last(), Some(PenEvent::Up(_))));
+    }
+
+    #[test]
+    fn penirq_without_touch_waits_again() {
+        let mut driver = ScriptedDriver::new(&[], 2);
+        let mut penirq = MockPin::fail_after(1);
+        let mut events = Vec::new();
+
+        let result = block_on(wait_for_touch(&mut driver, &mut penirq, &mut NoDelay::default(), 10, |e| events.push(e)));
+        assert_eq!(result, Err(PenIrqError::Pin(MockPinError)));
+        assert_eq!(driver.index, 3);
+        assert!(events.is_empty());
+    }
+}
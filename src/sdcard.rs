@@ -1,9 +1,12 @@
 extern crate alloc;
 
+use alloc::string::String;
 use alloc::vec::Vec;
+use core::fmt::{Debug, Write};
 
-use embedded_sdmmc::{BlockDevice, DirEntry, Error, RawDirectory, RawFile, RawVolume, ShortFileName, VolumeIdx};
+use embedded_sdmmc::{BlockDevice, DirEntry, Error, Mode, RawDirectory, RawFile, RawVolume, ShortFileName, VolumeIdx};
 use embedded_sdmmc::filesystem::ToShortFileName;
+use embedded_io::{ErrorKind, ErrorType, SeekFrom};
 
 pub struct FakeTimesource();
 
@@ -20,10 +23,65 @@ impl embedded_sdmmc::TimeSource for FakeTimesource {
     }
 }
 
+#[derive(Clone, Debug)]
+pub enum SdcardError<E: Debug> {
+    /// open_root_dir was not called or failed
+    NotMounted,
+    /// file or directory does not exist
+    NotFound,
+    /// write to a file opened for reading
+    ReadOnly,
+    /// the file does not fit into the buffer, needed is the file size
+    BufferTooSmall { needed: u32 },
+    /// the card did not answer or reported an error, e.g. it was removed
+    Device(E),
+    /// other errors of the FAT file system
+    Filesystem(Error<E>),
+}
+
+fn check_buffer_size<E: Debug>(file_size: u32, buffer_len: usize) -> Result<(), SdcardError<E>> {
+    if file_size as usize > buffer_len {
+        return Err(SdcardError::BufferTooSmall { needed: file_size });
+    }
+    Ok(())
+}
+
+impl<E: Debug> From<Error<E>> for SdcardError<E> {
+    fn from(error: Error<E>) -> Self {
+        match error {
+            Error::NotFound => SdcardError::NotFound,
+            Error::ReadOnly => SdcardError::ReadOnly,
+            Error::DeviceError(e) => SdcardError::Device(e),
+            e => SdcardError::Filesystem(e),
+        }
+    }
+}
+
+impl<E: Debug> embedded_io::Error for SdcardError<E> {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            SdcardError::NotMounted => ErrorKind::NotConnected,
+            SdcardError::NotFound => ErrorKind::NotFound,
+            SdcardError::ReadOnly => ErrorKind::PermissionDenied,
+            SdcardError::BufferTooSmall { .. } => ErrorKind::OutOfMemory,
+            SdcardError::Device(_) => ErrorKind::Other,
+            SdcardError::Filesystem(e) => match e {
+                Error::FileAlreadyExists | Error::DirAlreadyExists => ErrorKind::AlreadyExists,
+                Error::FilenameError(_) | Error::InvalidOffset => ErrorKind::InvalidInput,
+                Error::DiskFull | Error::NotEnoughSpace => ErrorKind::WriteZero,
+                Error::Unsupported => ErrorKind::Unsupported,
+                _ => ErrorKind::Other,
+            },
+        }
+    }
+}
+
 pub struct SdcardManager<D: BlockDevice> {
     volume_manager: embedded_sdmmc::VolumeManager<D, FakeTimesource>,
     root_volume: Option<RawVolume>,
     pub root_dir: Option<RawDirectory>,
+    /// current directory below the root directory
+    cwd: Vec<ShortFileName>,
 }
 
 impl<D: embedded_sdmmc::BlockDevice> SdcardManager<D> {
@@ -32,32 +90,75 @@ impl<D: embedded_sdmmc::BlockDevice> SdcardManager<D> {
             volume_manager: embedded_sdmmc::VolumeManager::new(sdcard, FakeTimesource()),
             root_volume: None,
             root_dir: None,
+            cwd: Vec::new(),
         }
     }
 
-    pub fn get_root_dir_entries(&mut self, file_list: &mut Vec<DirEntry>) -> Result<(), Error<D::Error>> {
-        if self.root_volume.is_some() && self.root_dir.is_some() {
-            self.volume_manager.iterate_dir(self.root_dir.unwrap(), |d| {
-                let dir = d.clone();
-                if !dir.attributes.is_directory() {
-                    file_list.push(dir);
-                }
-            }).unwrap();
+    pub fn is_mounted(&self) -> bool {
+        self.root_volume.is_some() && self.root_dir.is_some()
+    }
+
+    fn get_root_dir(&self) -> Result<RawDirectory, SdcardError<D::Error>> {
+        match (self.root_volume, self.root_dir) {
+            (Some(_), Some(root_dir)) => Ok(root_dir),
+            _ => Err(SdcardError::NotMounted),
         }
+    }
 
+    /// Files of dir without subdirectories
+    fn push_file_entries(&mut self, dir: RawDirectory, file_list: &mut Vec<DirEntry>) -> Result<(), SdcardError<D::Error>> {
+        self.volume_manager.iterate_dir(dir, |d| {
+            if !d.attributes.is_directory() {
+                file_list.push(d.clone());
+            }
+        })?;
         Ok(())
     }
 
-    pub fn load_file_into_buffer(&mut self, name: ShortFileName, buffer: &mut [u8]) -> Result<(), Error<D::Error>> {
-        if self.root_volume.is_some() && self.root_dir.is_some() {
-            let file = self.volume_manager.open_file_in_dir(self.root_dir.unwrap(), name, embedded_sdmmc::Mode::ReadOnly)?;
-            self.volume_manager.read(file, buffer)?;
-            self.volume_manager.close_file(file)?;
-        }
-        Ok(())
+    /// Reads the whole file into the start of buffer and closes it, also on errors
+    fn read_whole_file(&mut self, file: RawFile, buffer: &mut [u8]) -> Result<usize, SdcardError<D::Error>> {
+        let result = match self.volume_manager.file_length(file) {
+            Ok(file_size) => check_buffer_size(file_size, buffer.len())
+                .and_then(|()| Ok(self.volume_manager.read(file, buffer)?)),
+            Err(e) => Err(e.into()),
+        };
+        self.volume_manager.close_file(file)?;
+        result
+    }
+
+    /// Reads the whole file into a Vec of the file size and closes it
+    fn read_whole_file_to_vec(&mut self, file: RawFile) -> Result<Vec<u8>, SdcardError<D::Error>> {
+        let result = match self.volume_manager.file_length(file) {
+            Ok(file_size) => {
+                let mut data = alloc::vec![0u8; file_size as usize];
+                self.volume_manager.read(file, &mut data).map(|len| {
+                    data.truncate(len);
+                    data
+                })
+            }
+            Err(e) => Err(e),
+        };
+        self.volume_manager.close_file(file)?;
+        Ok(result?)
+    }
+
+    fn get_open_file_size(&mut self, file: RawFile) -> Result<u32, SdcardError<D::Error>> {
+        let file_size = self.volume_manager.file_length(file);
+        self.volume_manager.close_file(file)?;
+        Ok(file_size?)
+    }
+
+    pub fn get_root_dir_entries(&mut self, file_list: &mut Vec<DirEntry>) -> Result<(), SdcardError<D::Error>> {
+        let root_dir = self.get_root_dir()?;
+        self.push_file_entries(root_dir, file_list)
+    }
+
+    /// Returns the number of bytes read, BufferTooSmall if the file is larger than the buffer
+    pub fn load_file_into_buffer(&mut self, name: ShortFileName, buffer: &mut [u8]) -> Result<usize, SdcardError<D::Error>> {
+        self.load_root_dir_file_into_buffer(name, buffer)
     }
 
-    pub fn open_root_dir(&mut self) -> Result<(), Error<D::Error>> {
+    pub fn open_root_dir(&mut self) -> Result<(), SdcardError<D::Error>> {
         let volume0 = self.volume_manager.open_raw_volume(VolumeIdx(0))?;
         self.root_volume = Some(volume0);
         let root_dir = self.volume_manager.open_root_dir(volume0)?;
@@ -65,86 +166,323 @@ impl<D: embedded_sdmmc::BlockDevice> SdcardManager<D> {
         Ok(())
     }
 
-    pub fn close_root_dir(&mut self) -> Result<(), Error<D::Error>> {
-        if self.root_dir.is_some() {
-            self.volume_manager.close_dir(self.root_dir.unwrap())?;
+    pub fn close_root_dir(&mut self) -> Result<(), SdcardError<D::Error>> {
+        if let Some(root_dir) = self.root_dir.take() {
+            self.volume_manager.close_dir(root_dir)?;
         }
-        if self.root_volume.is_some() {
-            self.volume_manager.close_volume(self.root_volume.unwrap())?;
+        if let Some(root_volume) = self.root_volume.take() {
+            self.volume_manager.close_volume(root_volume)?;
         }
+        self.cwd.clear();
         Ok(())
     }
 
-    pub fn get_subdir_entries<N>(&mut self, dir: N, file_list: &mut Vec<DirEntry>) -> Result<(), Error<D::Error>> where N: ToShortFileName {
-        if self.root_volume.is_some() && self.root_dir.is_some() {
-            let sub_dir = self.volume_manager.open_dir(self.root_dir.unwrap(), dir)?;
-            self.volume_manager.iterate_dir(sub_dir, |d| {
-                let dir = d.clone();
-                if !dir.attributes.is_directory() {
-                    file_list.push(dir);
-                }
-            }).unwrap();
-            self.volume_manager.close_dir(sub_dir)?;
+    pub fn get_subdir_entries<N>(&mut self, dir: N, file_list: &mut Vec<DirEntry>) -> Result<(), SdcardError<D::Error>> where N: ToShortFileName {
+        let root_dir = self.get_root_dir()?;
+        let sub_dir = self.volume_manager.open_dir(root_dir, dir)?;
+        let result = self.push_file_entries(sub_dir, file_list);
+        self.volume_manager.close_dir(sub_dir)?;
+        result
+    }
+
+    /// Returns the number of bytes read, BufferTooSmall if the file is larger than the buffer
+    pub fn load_subdir_file_into_buffer<N>(&mut self, dir: N, name: N, buffer: &mut [u8]) -> Result<usize, SdcardError<D::Error>> where N: ToShortFileName {
+        let root_dir = self.get_root_dir()?;
+        let sub_dir = self.volume_manager.open_dir(root_dir, dir)?;
+        let result = match self.volume_manager.open_file_in_dir(sub_dir, name, Mode::ReadOnly) {
+            Ok(file) => self.read_whole_file(file, buffer),
+            Err(e) => Err(e.into()),
+        };
+        self.volume_manager.close_dir(sub_dir)?;
+        result
+    }
+
+    /// Returns the number of bytes read, BufferTooSmall if the file is larger than the buffer
+    pub fn load_root_dir_file_into_buffer<N>(&mut self, name: N, buffer: &mut [u8]) -> Result<usize, SdcardError<D::Error>> where N: ToShortFileName {
+        let root_dir = self.get_root_dir()?;
+        let file = self.volume_manager.open_file_in_dir(root_dir, name, Mode::ReadOnly)?;
+        self.read_whole_file(file, buffer)
+    }
+
+    pub fn load_root_dir_file_to_vec<N>(&mut self, name: N) -> Result<Vec<u8>, SdcardError<D::Error>> where N: ToShortFileName {
+        let root_dir = self.get_root_dir()?;
+        let file = self.volume_manager.open_file_in_dir(root_dir, name, Mode::ReadOnly)?;
+        self.read_whole_file_to_vec(file)
+    }
+
+    pub fn get_root_dir_file_size<N>(&mut self, name: N) -> Result<u32, SdcardError<D::Error>> where N: ToShortFileName {
+        let root_dir = self.get_root_dir()?;
+        let file = self.volume_manager.open_file_in_dir(root_dir, name, Mode::ReadOnly)?;
+        self.get_open_file_size(file)
+    }
+
+    /// Continues at the current position until EOF or the buffer is full, 0 at EOF
+    pub fn load_open_file_into_buffer(&mut self, file: RawFile, buffer: &mut [u8]) -> Result<usize, SdcardError<D::Error>> {
+        Ok(self.volume_manager.read(file, buffer)?)
+    }
+
+    pub fn close_open_file(&mut self, file: RawFile) -> Result<(), SdcardError<D::Error>>  {
+        Ok(self.volume_manager.close_file(file)?)
+    }
+
+    pub fn write_file_in_root_dir_from_buffer(&mut self, file: RawFile, buffer: &[u8]) -> Result<(), SdcardError<D::Error>> {
+        Ok(self.volume_manager.write(file, buffer)?)
+    }
+
+    pub fn open_file_in_root_dir_for_writing<N>(&mut self, name: N) -> Result<RawFile, SdcardError<D::Error>> where N: ToShortFileName {
+        let root_dir = self.get_root_dir()?;
+        Ok(self.volume_manager.open_file_in_dir(root_dir, name, Mode::ReadWriteCreateOrTruncate)?)
+    }
+    pub fn open_file_in_root_dir_for_reading<N>(&mut self, name: N) -> Result<RawFile, SdcardError<D::Error>> where N: ToShortFileName {
+        let root_dir = self.get_root_dir()?;
+        Ok(self.volume_manager.open_file_in_dir(root_dir, name, Mode::ReadOnly)?)
+    }
+
+    pub fn close_file(&mut self, file: RawFile) -> Result<(), SdcardError<D::Error>> {
+        Ok(self.volume_manager.close_file(file)?)
+    }
+
+    /// NotFound if the file does not exist
+    pub fn delete_file_in_root_dir<N>(&mut self, file: N) -> Result<(), SdcardError<D::Error>> where N: ToShortFileName {
+        let root_dir = self.get_root_dir()?;
+        Ok(self.volume_manager.delete_file_in_dir(root_dir, file)?)
+    }
+
+    /// Directory components of path, relative paths start at the current directory.
+    /// "." and ".." are resolved, ".." at the root stays at the root
+    fn resolve_dir_path(&self, path: &str) -> Result<Vec<ShortFileName>, SdcardError<D::Error>> {
+        let mut components = if path.starts_with('/') { Vec::new() } else { self.cwd.clone() };
+        for part in path.split('/').filter(|p| !p.is_empty() && *p != ".") {
+            if part == ".." {
+                components.pop();
+            } else {
+                components.push(ShortFileName::create_from_str(part).map_err(|e| SdcardError::Filesystem(Error::FilenameError(e)))?);
+            }
         }
+        Ok(components)
+    }
 
-        Ok(())
+    /// Splits a file path into its resolved directory and the file name
+    fn resolve_file_path(&self, path: &str) -> Result<(Vec<ShortFileName>, ShortFileName), SdcardError<D::Error>> {
+        let (dir, name) = match path.rsplit_once('/') {
+            Some(("", name)) => ("/", name),
+            Some((dir, name)) => (dir, name),
+            None => ("", path),
+        };
+        let name = ShortFileName::create_from_str(name).map_err(|e| SdcardError::Filesystem(Error::FilenameError(e)))?;
+        Ok((self.resolve_dir_path(dir)?, name))
     }
 
-    pub fn load_subdir_file_into_buffer<N>(&mut self, dir: N, name: N, buffer: &mut [u8]) -> Result<(), Error<D::Error>> where N: ToShortFileName {
-        if self.root_volume.is_some() && self.root_dir.is_some() {
-            let sub_dir = self.volume_manager.open_dir(self.root_dir.unwrap(), dir)?;
-            let file = self.volume_manager.open_file_in_dir(sub_dir, name, embedded_sdmmc::Mode::ReadOnly)?;
-            self.volume_manager.read(file, buffer)?;
-            self.volume_manager.close_file(file)?;
-            self.volume_manager.close_dir(sub_dir)?;
+    /// Opens the directory level by level, only one subdirectory is open at a time.
+    /// The root directory is returned as is, close the result with close_dir_unless_root
+    fn open_dir_components(&mut self, components: &[ShortFileName]) -> Result<RawDirectory, SdcardError<D::Error>> {
+        let root_dir = self.get_root_dir()?;
+        let mut dir = root_dir;
+        for name in components {
+            let sub_dir = self.volume_manager.open_dir(dir, name);
+            self.close_dir_unless_root(dir)?;
+            dir = sub_dir?;
+        }
+        Ok(dir)
+    }
+
+    fn close_dir_unless_root(&mut self, dir: RawDirectory) -> Result<(), SdcardError<D::Error>> {
+        if Some(dir) != self.root_dir {
+            self.volume_manager.close_dir(dir)?;
         }
         Ok(())
     }
 
-    pub fn load_root_dir_file_into_buffer<N>(&mut self, name: N, buffer: &mut [u8]) -> Result<(), Error<D::Error>> where N: ToShortFileName {
-        if self.root_volume.is_some() && self.root_dir.is_some() {
-            let file = self.volume_manager.open_file_in_dir(self.root_dir.unwrap(), name, embedded_sdmmc::Mode::ReadOnly)?;
-            self.volume_manager.read(file, buffer)?;
-            self.volume_manager.close_file(file)?;
+    /// Current directory as absolute path, e.g. "/ICONS/DARK"
+    pub fn get_cwd(&self) -> String {
+        let mut path = String::new();
+        for name in self.cwd.iter() {
+            let _ = write!(path, "/{}", name);
+        }
+        if path.is_empty() {
+            path.push('/');
         }
+        path
+    }
+
+    /// Changes the current directory, path is absolute or relative to the current directory
+    pub fn cd(&mut self, path: &str) -> Result<(), SdcardError<D::Error>> {
+        let components = self.resolve_dir_path(path)?;
+        let dir = self.open_dir_components(&components)?;
+        self.close_dir_unless_root(dir)?;
+        self.cwd = components;
         Ok(())
     }
 
-    pub fn load_open_file_into_buffer(&mut self, file: RawFile, buffer: &mut [u8]) -> Result<usize, Error<D::Error>> {
-        self.volume_manager.read(file, buffer)
+    /// Changes to the parent directory, returns false in the root directory
+    pub fn up(&mut self) -> bool {
+        self.cwd.pop().is_some()
+    }
+
+    /// Files and subdirectories of path without "." and ".."
+    pub fn get_dir_entries(&mut self, path: &str, entry_list: &mut Vec<DirEntry>) -> Result<(), SdcardError<D::Error>> {
+        let components = self.resolve_dir_path(path)?;
+        let dir = self.open_dir_components(&components)?;
+        let result = self.volume_manager.iterate_dir(dir, |entry| {
+            if entry.name != ShortFileName::this_dir() && entry.name != ShortFileName::parent_dir() {
+                entry_list.push(entry.clone());
+            }
+        });
+        self.close_dir_unless_root(dir)?;
+        Ok(result?)
     }
 
-    pub fn close_open_file(&mut self, file: RawFile) -> Result<(), Error<D::Error>>  {
-        self.volume_manager.close_file(file)
+    /// Files and subdirectories of the current directory
+    pub fn get_cwd_entries(&mut self, entry_list: &mut Vec<DirEntry>) -> Result<(), SdcardError<D::Error>> {
+        self.get_dir_entries(".", entry_list)
     }
 
-    pub fn write_file_in_root_dir_from_buffer(&mut self, file: RawFile, buffer: &[u8]) -> Result<(), Error<D::Error>> {
-        self.volume_manager.write(file, buffer)
+    /// Opens a file by path, e.g. "/icons/dark/wifi.png" or "dark/wifi.png" relative to the current directory
+    pub fn open_file_by_path(&mut self, path: &str, mode: Mode) -> Result<RawFile, SdcardError<D::Error>> {
+        let (components, name) = self.resolve_file_path(path)?;
+        let dir = self.open_dir_components(&components)?;
+        let file = self.volume_manager.open_file_in_dir(dir, name, mode);
+        self.close_dir_unless_root(dir)?;
+        Ok(file?)
     }
 
-    pub fn open_file_in_root_dir_for_writing<N>(&mut self, name: N) -> Result<RawFile, Error<D::Error>> where N: ToShortFileName {
-        if self.root_volume.is_some() && self.root_dir.is_some() {
-            return self.volume_manager.open_file_in_dir(self.root_dir.unwrap(), name, embedded_sdmmc::Mode::ReadWriteCreateOrTruncate);
-        }
-        Err(Error::NotFound)
+    /// Returns the number of bytes read, BufferTooSmall if the file is larger than the buffer
+    pub fn load_file_into_buffer_by_path(&mut self, path: &str, buffer: &mut [u8]) -> Result<usize, SdcardError<D::Error>> {
+        let file = self.open_file_by_path(path, Mode::ReadOnly)?;
+        self.read_whole_file(file, buffer)
+    }
+
+    pub fn load_file_to_vec_by_path(&mut self, path: &str) -> Result<Vec<u8>, SdcardError<D::Error>> {
+        let file = self.open_file_by_path(path, Mode::ReadOnly)?;
+        self.read_whole_file_to_vec(file)
+    }
+
+    /// Size in bytes, e.g. to allocate the buffer for load_file_into_buffer_by_path
+    pub fn get_file_size_by_path(&mut self, path: &str) -> Result<u32, SdcardError<D::Error>> {
+        let file = self.open_file_by_path(path, Mode::ReadOnly)?;
+        self.get_open_file_size(file)
+    }
+
+    /// Opens a file by path as SdcardFile. The file borrows the manager, so only one
+    /// SdcardFile can be open at a time. Call SdcardFile::close to get the close error,
+    /// dropping the file closes it and discards the error
+    pub fn open_file(&mut self, path: &str, mode: Mode) -> Result<SdcardFile<'_, D>, SdcardError<D::Error>> {
+        let file = self.open_file_by_path(path, mode)?;
+        Ok(SdcardFile {
+            sdcard: self,
+            file,
+            closed: false,
+        })
+    }
+
+    /// See open_file
+    pub fn open_file_for_reading(&mut self, path: &str) -> Result<SdcardFile<'_, D>, SdcardError<D::Error>> {
+        self.open_file(path, Mode::ReadOnly)
+    }
+
+    /// Creates the file or truncates an existing one, see open_file. Use close to
+    /// know if the new size was written to the directory entry
+    pub fn open_file_for_writing(&mut self, path: &str) -> Result<SdcardFile<'_, D>, SdcardError<D::Error>> {
+        self.open_file(path, Mode::ReadWriteCreateOrTruncate)
+    }
+
+    /// Creates the file or writes at the end of an existing one, see open_file
+    pub fn open_file_for_appending(&mut self, path: &str) -> Result<SdcardFile<'_, D>, SdcardError<D::Error>> {
+        self.open_file(path, Mode::ReadWriteCreateOrAppend)
+    }
+}
+
+/// Open file implementing embedded_io Read, Write and Seek. It holds the mutable borrow
+/// of SdcardManager, so only one file can be open at a time. The directory entry with
+/// the new size is only written on close, flush does nothing. Drop also closes the file
+/// but discards the error, call close after writing
+pub struct SdcardFile<'a, D: BlockDevice> {
+    sdcard: &'a mut SdcardManager<D>,
+    file: RawFile,
+    closed: bool,
+}
+
+impl<D: BlockDevice> SdcardFile<'_, D> {
+    pub fn get_size(&self) -> Result<u32, SdcardError<D::Error>> {
+        Ok(self.sdcard.volume_manager.file_length(self.file)?)
     }
-    pub fn open_file_in_root_dir_for_reading<N>(&mut self, name: N) -> Result<RawFile, Error<D::Error>> where N: ToShortFileName {
-        if self.root_volume.is_some() && self.root_dir.is_some() {
-            return self.volume_manager.open_file_in_dir(self.root_dir.unwrap(), name, embedded_sdmmc::Mode::ReadOnly);
+
+    pub fn get_offset(&self) -> Result<u32, SdcardError<D::Error>> {
+        Ok(self.sdcard.volume_manager.file_offset(self.file)?)
+    }
+
+    pub fn is_eof(&self) -> Result<bool, SdcardError<D::Error>> {
+        Ok(self.sdcard.volume_manager.file_eof(self.file)?)
+    }
+
+    /// Closes the file and writes the directory entry, returns the error Drop would discard
+    pub fn close(mut self) -> Result<(), SdcardError<D::Error>> {
+        self.closed = true;
+        self.sdcard.close_file(self.file)
+    }
+}
+
+impl<D: BlockDevice> Drop for SdcardFile<'_, D> {
+    fn drop(&mut self) {
+        if !self.closed {
+            let _ = self.sdcard.volume_manager.close_file(self.file);
         }
-        Err(Error::NotFound)
     }
+}
+
+impl<D: BlockDevice> ErrorType for SdcardFile<'_, D> {
+    type Error = SdcardError<D::Error>;
+}
+
+impl<D: BlockDevice> embedded_io::Read for SdcardFile<'_, D> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        Ok(self.sdcard.volume_manager.read(self.file, buf)?)
+    }
+}
 
-    pub fn close_file(&mut self, file: RawFile) -> Result<(), Error<D::Error>> {
-        self.volume_manager.close_file(file)
+impl<D: BlockDevice> embedded_io::Write for SdcardFile<'_, D> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        self.sdcard.volume_manager.write(self.file, buf)?;
+        Ok(buf.len())
     }
 
-    pub fn delete_file_in_root_dir<N>(&mut self, file: N) -> Result<(), Error<D::Error>> where N: ToShortFileName {
-        if self.root_volume.is_some() && self.root_dir.is_some() {
-            return match self.volume_manager.delete_file_in_dir(self.root_dir.unwrap(), file) {
-                _ => Ok(()),
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+impl<D: BlockDevice> embedded_io::Seek for SdcardFile<'_, D> {
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64, Self::Error> {
+        let invalid_offset = |_| SdcardError::Filesystem(Error::InvalidOffset);
+        let volume_manager = &mut self.sdcard.volume_manager;
+        match pos {
+            SeekFrom::Start(offset) => {
+                volume_manager.file_seek_from_start(self.file, u32::try_from(offset).map_err(invalid_offset)?)?
+            }
+            SeekFrom::Current(offset) => {
+                volume_manager.file_seek_from_current(self.file, i32::try_from(offset).map_err(invalid_offset)?)?
+            }
+            SeekFrom::End(offset) if offset <= 0 => {
+                volume_manager.file_seek_from_end(self.file, u32::try_from(offset.unsigned_abs()).map_err(invalid_offset)?)?
             }
+            SeekFrom::End(_) => return Err(SdcardError::Filesystem(Error::InvalidOffset)),
         }
-        Err(Error::NotFound)
+        Ok(volume_manager.file_offset(self.file)? as u64)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use embedded_io::Error as _;
+
+    use super::*;
+
+    #[test]
+    fn files_larger_than_the_buffer_are_rejected() {
+        assert!(check_buffer_size::<()>(0, 0).is_ok());
+        assert!(check_buffer_size::<()>(512, 512).is_ok());
+        let error = check_buffer_size::<()>(513, 512).unwrap_err();
+        assert!(matches!(error, SdcardError::BufferTooSmall { needed: 513 }));
+        assert_eq!(error.kind(), ErrorKind::OutOfMemory);
+    }
+}
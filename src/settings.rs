@@ -0,0 +1,320 @@
+//! Settings record stored on the SD card.
+//!
+//! embedded-sdmmc can not rename files, so saving alternates between two slot files.
+//! Every record has a sequence number and a CRC, loading picks the newest valid slot,
+//! so an interrupted save leaves the previous settings readable.
+
+use embedded_sdmmc::BlockDevice;
+
+use crate::sdcard::{SdcardError, SdcardManager};
+use crate::touch_mapper::{CalibrationMatrix, Orientation, TouchPosMapper};
+
+pub const SETTINGS_SLOT_FILES: [&str; 2] = ["SETTINGS.A", "SETTINGS.B"];
+
+const RECORD_MAGIC: [u8; 4] = *b"ESET";
+pub const RECORD_VERSION: u16 = 1;
+/// magic, version, sequence and payload length
+const HEADER_LEN: usize = 12;
+const CRC_LEN: usize = 4;
+/// flags, calibration matrix, theme, brightness and orientation
+const PAYLOAD_V1_LEN: usize = 1 + 6 * 4 + 3;
+const MAX_PAYLOAD_LEN: usize = 128;
+const MAX_RECORD_LEN: usize = HEADER_LEN + MAX_PAYLOAD_LEN + CRC_LEN;
+
+const FLAG_CALIBRATION: u8 = 0x01;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Settings {
+    /// None until the touch panel was calibrated
+    pub calibration: Option<CalibrationMatrix>,
+    /// index into the themes of the application
+    pub theme_index: u8,
+    /// display backlight, 0..=255
+    pub brightness: u8,
+    pub orientation: Orientation,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            calibration: None,
+            theme_index: 0,
+            brightness: 255,
+            orientation: Orientation::Deg0,
+        }
+    }
+}
+
+impl Settings {
+    fn to_payload(self) -> [u8; PAYLOAD_V1_LEN] {
+        let mut payload = [0u8; PAYLOAD_V1_LEN];
+        if let Some(calibration) = self.calibration {
+            payload[0] |= FLAG_CALIBRATION;
+            let values = [calibration.a, calibration.b, calibration.c, calibration.d, calibration.e, calibration.f];
+            for (index, value) in values.iter().enumerate() {
+                payload[1 + index * 4..5 + index * 4].copy_from_slice(&value.to_le_bytes());
+            }
+        }
+        payload[25] = self.theme_index;
+        payload[26] = self.brightness;
+        payload[27] = self.orientation.as_u8();
+        payload
+    }
+
+    /// Mapper from the stored calibration, or from the touch limits of the driver if the panel
+    /// was not calibrated. None if the limits are not increasing
+    pub fn get_touch_mapper(&self, width: u16, height: u16, touch_limit_x: (u16, u16),
+                            touch_limit_y: (u16, u16)) -> Option<TouchPosMapper> {
+        match self.calibration {
+            Some(calibration) => TouchPosMapper::try_new_calibrated(width, height, calibration),
+            None => TouchPosMapper::try_new(width, height, touch_limit_x, touch_limit_y),
+        }
+    }
+
+    /// Newer record versions only append fields, so their known prefix can be read
+    fn from_payload(payload: &[u8]) -> Option<Self> {
+        if payload.len() < PAYLOAD_V1_LEN {
+            return None;
+        }
+        let value = |index: usize| {
+            f32::from_le_bytes([payload[1 + index * 4], payload[2 + index * 4], payload[3 + index * 4], payload[4 + index * 4]])
+        };
+        let calibration = if payload[0] & FLAG_CALIBRATION != 0 {
+            Some(CalibrationMatrix {
+                a: value(0),
+                b: value(1),
+                c: value(2),
+                d: value(3),
+                e: value(4),
+                f: value(5),
+            })
+        } else {
+            None
+        };
+        // a matrix that can not map positions is treated as not calibrated
+        let calibration = calibration.filter(|c| c.is_valid());
+        Some(Settings {
+            calibration,
+            theme_index: payload[25],
+            brightness: payload[26],
+            orientation: Orientation::from_u8(payload[27]).unwrap_or_default(),
+        })
+    }
+}
+
+/// CRC-32 (IEEE 802.3)
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn encode_record(settings: &Settings, sequence: u32, buffer: &mut [u8; MAX_RECORD_LEN]) -> usize {
+    let payload = settings.to_payload();
+    buffer[0..4].copy_from_slice(&RECORD_MAGIC);
+    buffer[4..6].copy_from_slice(&RECORD_VERSION.to_le_bytes());
+    buffer[6..10].copy_from_slice(&sequence.to_le_bytes());
+    buffer[10..12].copy_from_slice(&(payload.len() as u16).to_le_bytes());
+    buffer[HEADER_LEN..HEADER_LEN + payload.len()].copy_from_slice(&payload);
+    let len = HEADER_LEN + payload.len();
+    let crc = crc32(&buffer[..len]);
+    buffer[len..len + CRC_LEN].copy_from_slice(&crc.to_le_bytes());
+    len + CRC_LEN
+}
+
+/// Sequence number and settings, None if the record is truncated or corrupted
+fn decode_record(record: &[u8]) -> Option<(u32, Settings)> {
+    if record.len() < HEADER_LEN + CRC_LEN || record[0..4] != RECORD_MAGIC {
+        return None;
+    }
+    let version = u16::from_le_bytes([record[4], record[5]]);
+    let sequence = u32::from_le_bytes([record[6], record[7], record[8], record[9]]);
+    let payload_len = u16::from_le_bytes([record[10], record[11]]) as usize;
+    if version < RECORD_VERSION || payload_len > MAX_PAYLOAD_LEN || record.len() < HEADER_LEN + payload_len + CRC_LEN {
+        return None;
+    }
+    let len = HEADER_LEN + payload_len;
+    let crc = u32::from_le_bytes([record[len], record[len + 1], record[len + 2], record[len + 3]]);
+    if crc != crc32(&record[..len]) {
+        return None;
+    }
+    Settings::from_payload(&record[HEADER_LEN..len]).map(|settings| (sequence, settings))
+}
+
+/// Slot, sequence number and settings of the newest valid record, the sequence number may wrap
+fn newest_record(records: &[Option<(u32, Settings)>]) -> Option<(usize, u32, Settings)> {
+    let mut newest: Option<(usize, u32, Settings)> = None;
+    for (slot, record) in records.iter().enumerate() {
+        if let Some((sequence, settings)) = *record {
+            let is_newer = match newest {
+                Some((_, newest_sequence, _)) => sequence.wrapping_sub(newest_sequence) as i32 > 0,
+                None => true,
+            };
+            if is_newer {
+                newest = Some((slot, sequence, settings));
+            }
+        }
+    }
+    newest
+}
+
+/// Loads and saves Settings in the root directory, the root directory must be open
+pub struct SettingsStore {
+    sequence: u32,
+    /// slot of the newest valid record
+    slot: Option<usize>,
+}
+
+impl SettingsStore {
+    pub fn new() -> Self {
+        SettingsStore {
+            sequence: 0,
+            slot: None,
+        }
+    }
+
+    fn read_slot<D: BlockDevice>(sdcard: &mut SdcardManager<D>, slot: usize) -> Result<Option<(u32, Settings)>, SdcardError<D::Error>> {
+        let file = match sdcard.open_file_in_root_dir_for_reading(SETTINGS_SLOT_FILES[slot]) {
+            Ok(file) => file,
+            Err(SdcardError::NotFound) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let mut buffer = [0u8; MAX_RECORD_LEN];
+        let read = sdcard.load_open_file_into_buffer(file, &mut buffer);
+        sdcard.close_file(file)?;
+        Ok(decode_record(&buffer[..read?]))
+    }
+
+    /// Newest valid settings, the defaults if no slot contains a valid record.
+    /// Errors are only returned for card errors, not for missing or corrupted files
+    pub fn load<D: BlockDevice>(&mut self, sdcard: &mut SdcardManager<D>) -> Result<Settings, SdcardError<D::Error>> {
+        let mut records = [None; SETTINGS_SLOT_FILES.len()];
+        for (slot, record) in records.iter_mut().enumerate() {
+            *record = Self::read_slot(sdcard, slot)?;
+        }
+        match newest_record(&records) {
+            Some((slot, sequence, settings)) => {
+                self.slot = Some(slot);
+                self.sequence = sequence;
+                Ok(settings)
+            }
+            None => {
+                self.slot = None;
+                self.sequence = 0;
+                Ok(Settings::default())
+            }
+        }
+    }
+
+    /// Writes the slot not holding the newest record, call load first so that
+    /// the newest record is known
+    pub fn save<D: BlockDevice>(&mut self, sdcard: &mut SdcardManager<D>, settings: &Settings) -> Result<(), SdcardError<D::Error>> {
+        let slot = match self.slot {
+            Some(slot) => (slot + 1) % SETTINGS_SLOT_FILES.len(),
+            None => 0,
+        };
+        let sequence = self.sequence.wrapping_add(1);
+        let mut buffer = [0u8; MAX_RECORD_LEN];
+        let len = encode_record(settings, sequence, &mut buffer);
+
+        let file = sdcard.open_file_in_root_dir_for_writing(SETTINGS_SLOT_FILES[slot])?;
+        let written = sdcard.write_file_in_root_dir_from_buffer(file, &buffer[..len]);
+        sdcard.close_file(file)?;
+        written?;
+
+        self.slot = Some(slot);
+        self.sequence = sequence;
+        Ok(())
+    }
+}
+
+impl Default for SettingsStore {
+    fn default() -> Self {
+        SettingsStore::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn calibrated_settings() -> Settings {
+        Settings {
+            calibration: Some(CalibrationMatrix { a: 0.08, b: -0.002, c: -12.5, d: 0.001, e: 0.09, f: -20.0 }),
+            theme_index: 2,
+            brightness: 128,
+            orientation: Orientation::Deg270,
+        }
+    }
+
+    fn record(settings: &Settings, sequence: u32) -> ([u8; MAX_RECORD_LEN], usize) {
+        let mut buffer = [0u8; MAX_RECORD_LEN];
+        let len = encode_record(settings, sequence, &mut buffer);
+        (buffer, len)
+    }
+
+    #[test]
+    fn record_round_trip() {
+        for settings in [Settings::default(), calibrated_settings()] {
+            let (buffer, len) = record(&settings, 7);
+            assert_eq!(decode_record(&buffer[..len]), Some((7, settings)));
+        }
+    }
+
+    #[test]
+    fn corrupted_or_truncated_records_are_rejected() {
+        let (buffer, len) = record(&calibrated_settings(), 7);
+        for index in [0, 5, 8, HEADER_LEN + 3, len - 1] {
+            let mut corrupted = buffer;
+            corrupted[index] ^= 0x10;
+            assert_eq!(decode_record(&corrupted[..len]), None);
+        }
+        assert_eq!(decode_record(&buffer[..len - 1]), None);
+        assert_eq!(decode_record(&[]), None);
+    }
+
+    #[test]
+    fn load_falls_back_to_the_other_slot() {
+        let older = Settings { brightness: 10, ..Settings::default() };
+        let newer = Settings { brightness: 20, ..Settings::default() };
+        let (older_record, older_len) = record(&older, 4);
+        let (mut newer_record, newer_len) = record(&newer, 5);
+
+        let slots = [decode_record(&older_record[..older_len]), decode_record(&newer_record[..newer_len])];
+        assert_eq!(newest_record(&slots), Some((1, 5, newer)));
+
+        // interrupted save of the newer slot
+        newer_record[HEADER_LEN + 26] = 0;
+        let slots = [decode_record(&older_record[..older_len]), decode_record(&newer_record[..newer_len])];
+        assert_eq!(newest_record(&slots), Some((0, 4, older)));
+
+        assert_eq!(newest_record(&[None, None]), None);
+    }
+
+    #[test]
+    fn sequence_number_wraps() {
+        let slots = [Some((u32::MAX, Settings::default())), Some((0, calibrated_settings()))];
+        assert_eq!(newest_record(&slots).map(|(slot, _, _)| slot), Some(1));
+    }
+
+    #[test]
+    fn invalid_calibration_falls_back_to_the_limits() {
+        let settings = Settings {
+            calibration: Some(CalibrationMatrix { a: f32::NAN, b: 0.0, c: 0.0, d: 0.0, e: 1.0, f: 0.0 }),
+            ..Settings::default()
+        };
+        let (buffer, len) = record(&settings, 1);
+        let (_, loaded) = decode_record(&buffer[..len]).unwrap();
+        assert_eq!(loaded.calibration, None);
+
+        let mapper = loaded.get_touch_mapper(240, 320, (0, 4095), (0, 4095)).unwrap();
+        assert_eq!(mapper.get_calibration(), None);
+        assert!(loaded.get_touch_mapper(240, 320, (4095, 0), (0, 4095)).is_none());
+        assert!(calibrated_settings().get_touch_mapper(240, 320, (4095, 0), (0, 4095)).unwrap().get_calibration().is_some());
+    }
+}
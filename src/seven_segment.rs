@@ -0,0 +1,396 @@
+extern crate alloc;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Write;
+
+use eg_seven_segment::SevenSegmentStyle;
+use embedded_graphics::draw_target::DrawTarget;
+use embedded_graphics::Drawable;
+use embedded_graphics::geometry::{Point, Size};
+use embedded_graphics::pixelcolor::Rgb565;
+use embedded_graphics::primitives::Rectangle;
+use embedded_graphics::text::{Baseline, Text};
+use embedded_sdmmc::TimeSource;
+
+use crate::graphics::Theme;
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum SegmentCell {
+    Digit,
+    Colon,
+    DecimalPoint,
+}
+
+impl SegmentCell {
+    fn from_char(c: char) -> Self {
+        match c {
+            ':' => SegmentCell::Colon,
+            '.' => SegmentCell::DecimalPoint,
+            _ => SegmentCell::Digit,
+        }
+    }
+
+    fn get_char(&self) -> char {
+        match self {
+            SegmentCell::Digit => '8',
+            SegmentCell::Colon => ':',
+            SegmentCell::DecimalPoint => '.',
+        }
+    }
+}
+
+/// Fixed layout seven segment display, the layout is given as a pattern like
+/// "88:88" or "888.8" where every other character than ':' and '.' is a digit
+pub struct SevenSegmentDisplay {
+    pos: Point,
+    style: SevenSegmentStyle<Rgb565>,
+    background_color: Rgb565,
+    cells: Vec<SegmentCell>,
+    cell_pos_x: Vec<i32>,
+    digits: Vec<char>,
+    visible: Vec<Option<char>>,
+    separators_visible: bool,
+    leading_zeros: bool,
+}
+
+impl SevenSegmentDisplay {
+    pub fn new(pattern: &str, position: Point, style: SevenSegmentStyle<Rgb565>, theme: &Theme) -> Self {
+        let cells: Vec<SegmentCell> = pattern.chars().map(SegmentCell::from_char).collect();
+        let mut cell_pos_x = Vec::with_capacity(cells.len());
+        let mut x = position.x;
+        for cell in cells.iter() {
+            cell_pos_x.push(x);
+            x += (Self::get_cell_width(&style, *cell) + style.digit_spacing) as i32;
+        }
+        let digit_count = cells.iter().filter(|c| **c == SegmentCell::Digit).count();
+        SevenSegmentDisplay {
+            pos: position,
+            style,
+            background_color: theme.screen_background_color,
+            visible: alloc::vec![None; cells.len()],
+            cells,
+            cell_pos_x,
+            digits: alloc::vec![' '; digit_count],
+            separators_visible: true,
+            leading_zeros: true,
+        }
+    }
+
+    pub fn new_numeric(digits: usize, decimals: usize, position: Point, style: SevenSegmentStyle<Rgb565>, theme: &Theme) -> Self {
+        let mut pattern = String::new();
+        for index in 0..digits {
+            if decimals != 0 && index == digits - decimals.min(digits) {
+                pattern.push('.');
+            }
+            pattern.push('8');
+        }
+        SevenSegmentDisplay::new(pattern.as_str(), position, style, theme)
+    }
+
+    fn get_cell_width(style: &SevenSegmentStyle<Rgb565>, cell: SegmentCell) -> u32 {
+        match cell {
+            SegmentCell::Digit => style.digit_size.width,
+            _ => style.segment_width,
+        }
+    }
+
+    pub fn get_bounding_box(&self) -> Rectangle {
+        let width = self.cells.iter()
+            .map(|c| Self::get_cell_width(&self.style, *c) + self.style.digit_spacing)
+            .sum::<u32>()
+            .saturating_sub(self.style.digit_spacing);
+        Rectangle::new(self.pos, Size::new(width, self.style.digit_size.height))
+    }
+
+    pub fn get_digit_count(&self) -> usize {
+        self.digits.len()
+    }
+
+    fn get_decimals(&self) -> usize {
+        match self.cells.iter().position(|c| *c == SegmentCell::DecimalPoint) {
+            Some(index) => self.cells[index..].iter().filter(|c| **c == SegmentCell::Digit).count(),
+            None => 0,
+        }
+    }
+
+    pub fn set_leading_zeros(&mut self, leading_zeros: bool) {
+        self.leading_zeros = leading_zeros;
+    }
+
+    /// Forces a full redraw on the next update
+    pub fn invalidate(&mut self) {
+        self.visible.iter_mut().for_each(|c| *c = None);
+    }
+
+    pub fn set_separators_visible<D>(&mut self, display: &mut D, visible: bool) -> Result<(), D::Error>
+        where D: DrawTarget<Color=Rgb565> {
+        self.separators_visible = visible;
+        self.update(display)
+    }
+
+    /// Shows the characters in digits on the digit cells from left to right,
+    /// missing characters are shown blank
+    pub fn show_digits<D>(&mut self, display: &mut D, digits: &str) -> Result<(), D::Error>
+        where D: DrawTarget<Color=Rgb565> {
+        let mut chars = digits.chars();
+        for digit in self.digits.iter_mut() {
+            *digit = chars.next().unwrap_or(' ');
+        }
+        self.update(display)
+    }
+
+    /// Shows a fixed point value, value is the number scaled by 10^decimals.
+    /// Values that do not fit are shown as dashes
+    pub fn show_number<D>(&mut self, display: &mut D, value: i32) -> Result<(), D::Error>
+        where D: DrawTarget<Color=Rgb565> {
+        let digit_count = self.digits.len();
+        let min_digits = self.get_decimals() + 1;
+        let mut text = String::new();
+        let _ = write!(text, "{}", value.unsigned_abs());
+        while text.len() < min_digits {
+            text.insert(0, '0');
+        }
+        if value < 0 {
+            if self.leading_zeros {
+                while text.len() < digit_count.saturating_sub(1) {
+                    text.insert(0, '0');
+                }
+            }
+            text.insert(0, '-');
+        }
+        if text.len() > digit_count {
+            text = "-".repeat(digit_count);
+        }
+        let fill = if self.leading_zeros && value >= 0 { '0' } else { ' ' };
+        while text.len() < digit_count {
+            text.insert(0, fill);
+        }
+        self.show_digits(display, text.as_str())
+    }
+
+    pub fn show_float<D>(&mut self, display: &mut D, value: f32) -> Result<(), D::Error>
+        where D: DrawTarget<Color=Rgb565> {
+        let mut scaled = value;
+        for _ in 0..self.get_decimals() {
+            scaled *= 10.0;
+        }
+        let rounded = if scaled < 0.0 { scaled - 0.5 } else { scaled + 0.5 };
+        self.show_number(display, rounded as i32)
+    }
+
+    pub fn draw<D>(&mut self, display: &mut D) -> Result<(), D::Error>
+        where D: DrawTarget<Color=Rgb565> {
+        self.invalidate();
+        self.update(display)
+    }
+
+    /// Redraws only the cells that changed since the last update
+    fn update<D>(&mut self, display: &mut D) -> Result<(), D::Error>
+        where D: DrawTarget<Color=Rgb565> {
+        let mut digit_index = 0;
+        for index in 0..self.cells.len() {
+            let cell = self.cells[index];
+            let c = match cell {
+                SegmentCell::Digit => {
+                    digit_index += 1;
+                    self.digits[digit_index - 1]
+                }
+                _ => if self.separators_visible { cell.get_char() } else { ' ' },
+            };
+            if self.visible[index] == Some(c) {
+                continue;
+            }
+            self.draw_cell(display, index, c)?;
+            self.visible[index] = Some(c);
+        }
+        Ok(())
+    }
+
+    fn draw_cell<D>(&self, display: &mut D, index: usize, c: char) -> Result<(), D::Error>
+        where D: DrawTarget<Color=Rgb565> {
+        let cell = self.cells[index];
+        let cell_pos = Point::new(self.cell_pos_x[index], self.pos.y);
+        let cell_size = Size::new(Self::get_cell_width(&self.style, cell), self.style.digit_size.height);
+        display.fill_solid(&Rectangle::new(cell_pos, cell_size), self.background_color)?;
+        if c == ' ' && cell != SegmentCell::Digit {
+            return Ok(());
+        }
+        let mut buf = [0u8; 4];
+        Text::with_baseline(c.encode_utf8(&mut buf), cell_pos, self.style, Baseline::Top)
+            .draw(display)?;
+        Ok(())
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum ClockFormat {
+    /// HH:MM
+    HourMinute,
+    /// HH:MM:SS
+    HourMinuteSecond,
+    /// DD.MM
+    DayMonth,
+    /// DD.MM.YYYY
+    DayMonthYear,
+}
+
+impl ClockFormat {
+    fn get_pattern(&self) -> &'static str {
+        match self {
+            ClockFormat::HourMinute => "88:88",
+            ClockFormat::HourMinuteSecond => "88:88:88",
+            ClockFormat::DayMonth => "88.88",
+            ClockFormat::DayMonthYear => "88.88.8888",
+        }
+    }
+
+    fn is_time(&self) -> bool {
+        matches!(self, ClockFormat::HourMinute | ClockFormat::HourMinuteSecond)
+    }
+}
+
+pub struct SevenSegmentClock {
+    segment_display: SevenSegmentDisplay,
+    format: ClockFormat,
+    blink_separators: bool,
+}
+
+impl SevenSegmentClock {
+    pub fn new(format: ClockFormat, position: Point, style: SevenSegmentStyle<Rgb565>, theme: &Theme) -> Self {
+        SevenSegmentClock {
+            segment_display: SevenSegmentDisplay::new(format.get_pattern(), position, style, theme),
+            format,
+            blink_separators: false,
+        }
+    }
+
+    /// Blank the hour leading zero, e.g. " 9:41" instead of "09:41"
+    pub fn set_leading_zeros(&mut self, leading_zeros: bool) {
+        self.segment_display.set_leading_zeros(leading_zeros);
+    }
+
+    /// Separators are hidden on odd seconds, only used for time formats
+    pub fn set_blink_separators(&mut self, blink_separators: bool) {
+        self.blink_separators = blink_separators;
+    }
+
+    pub fn get_bounding_box(&self) -> Rectangle {
+        self.segment_display.get_bounding_box()
+    }
+
+    pub fn draw<D>(&mut self, display: &mut D) -> Result<(), D::Error>
+        where D: DrawTarget<Color=Rgb565> {
+        self.segment_display.draw(display)
+    }
+
+    pub fn update<D, T>(&mut self, display: &mut D, time_source: &T) -> Result<(), D::Error>
+        where D: DrawTarget<Color=Rgb565>, T: TimeSource {
+        let timestamp = time_source.get_timestamp();
+        let mut text = String::new();
+        match self.format {
+            ClockFormat::HourMinute | ClockFormat::HourMinuteSecond => {
+                if self.segment_display.leading_zeros {
+                    let _ = write!(text, "{:02}{:02}", timestamp.hours, timestamp.minutes);
+                } else {
+                    let _ = write!(text, "{:2}{:02}", timestamp.hours, timestamp.minutes);
+                }
+                if self.format == ClockFormat::HourMinuteSecond {
+                    let _ = write!(text, "{:02}", timestamp.seconds);
+                }
+            }
+            ClockFormat::DayMonth | ClockFormat::DayMonthYear => {
+                let _ = write!(text, "{:02}{:02}", timestamp.zero_indexed_day + 1, timestamp.zero_indexed_month + 1);
+                if self.format == ClockFormat::DayMonthYear {
+                    let _ = write!(text, "{:04}", 1970 + timestamp.year_since_1970 as u16);
+                }
+            }
+        }
+        self.segment_display.separators_visible = !(self.blink_separators && self.format.is_time() && timestamp.seconds % 2 == 1);
+        self.segment_display.show_digits(display, text.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use eg_seven_segment::SevenSegmentStyleBuilder;
+    use embedded_graphics::primitives::PointsIter;
+
+    use crate::memory_display::MemoryDisplay;
+
+    use super::*;
+
+    const SEGMENT_COLOR: Rgb565 = Rgb565::new(31, 0, 0);
+
+    fn style() -> SevenSegmentStyle<Rgb565> {
+        SevenSegmentStyleBuilder::new()
+            .digit_size(Size::new(12, 24))
+            .digit_spacing(2)
+            .segment_width(3)
+            .segment_color(SEGMENT_COLOR)
+            .build()
+    }
+
+    fn shown(segment_display: &SevenSegmentDisplay) -> String {
+        segment_display.digits.iter().collect()
+    }
+
+    fn count_segment_pixels(display: &MemoryDisplay, segment_display: &SevenSegmentDisplay, index: usize) -> usize {
+        let cell_size = Size::new(SevenSegmentDisplay::get_cell_width(&segment_display.style, segment_display.cells[index]),
+                                  segment_display.style.digit_size.height);
+        Rectangle::new(Point::new(segment_display.cell_pos_x[index], segment_display.pos.y), cell_size)
+            .points()
+            .filter(|pos| display.get_pixel(*pos) == Some(SEGMENT_COLOR))
+            .count()
+    }
+
+    #[test]
+    fn numbers_are_padded_to_the_digit_count() {
+        let mut display = MemoryDisplay::new(80, 24);
+        let theme = Theme::new_dark_theme();
+        let mut segment_display = SevenSegmentDisplay::new_numeric(4, 1, Point::zero(), style(), &theme);
+        assert_eq!(segment_display.get_digit_count(), 4);
+        assert_eq!(segment_display.get_decimals(), 1);
+        assert_eq!(segment_display.get_bounding_box().size, Size::new(4 * 12 + 3 + 4 * 2, 24));
+
+        for (value, leading_zeros, expected) in [
+            (123, true, "0123"),
+            (123, false, " 123"),
+            (3, false, "  03"),
+            (-5, true, "-005"),
+            (-5, false, " -05"),
+            (12345, true, "----"),
+            (-1000, true, "----"),
+        ] {
+            segment_display.set_leading_zeros(leading_zeros);
+            segment_display.show_number(&mut display, value).unwrap();
+            assert_eq!(shown(&segment_display), expected, "{} leading zeros {}", value, leading_zeros);
+        }
+        segment_display.set_leading_zeros(true);
+        segment_display.show_float(&mut display, -1.25).unwrap();
+        assert_eq!(shown(&segment_display), "-013");
+    }
+
+    #[test]
+    fn only_changed_cells_are_redrawn() {
+        let mut display = MemoryDisplay::new(80, 24);
+        let theme = Theme::new_dark_theme();
+        let mut segment_display = SevenSegmentDisplay::new("88:88", Point::zero(), style(), &theme);
+        segment_display.show_digits(&mut display, "1234").unwrap();
+        for index in 0..5 {
+            assert!(count_segment_pixels(&display, &segment_display, index) > 0);
+        }
+
+        // a pixel drawn over the first digit survives updates of the other cells
+        display.fill_solid(&Rectangle::new(Point::new(1, 1), Size::new(1, 1)), theme.highlight_color).unwrap();
+        segment_display.show_digits(&mut display, "1235").unwrap();
+        segment_display.set_separators_visible(&mut display, false).unwrap();
+        assert_eq!(display.get_pixel(Point::new(1, 1)), Some(theme.highlight_color));
+        assert_eq!(count_segment_pixels(&display, &segment_display, 2), 0);
+
+        segment_display.draw(&mut display).unwrap();
+        assert_eq!(display.get_pixel(Point::new(1, 1)), Some(theme.screen_background_color));
+        segment_display.show_digits(&mut display, "").unwrap();
+        assert_eq!(display.count_pixels(SEGMENT_COLOR), 0);
+    }
+}
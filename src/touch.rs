@@ -0,0 +1,293 @@
+//! Controller independent touch samples, implemented by every touch driver of the crate
+
+use embedded_hal::i2c::I2c as BlockingI2c;
+use embedded_hal_async::i2c::I2c;
+use embedded_hal_async::spi::SpiDevice;
+
+use crate::ft6236_asynch::{EventType, PointEvent, TouchData};
+use crate::resistive_touch::{PenEvent, ResistiveSample};
+use crate::touch_mapper::{Orientation, TouchPosMapper};
+
+/// Most touch points reported by a supported controller
+pub const MAX_TOUCH_POINTS: usize = 5;
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TouchPhase {
+    Down,
+    Move,
+    /// last position before the touch was lifted
+    Up,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TouchSample {
+    pub id: u8,
+    pub x: u16,
+    pub y: u16,
+    /// controller specific, higher means more pressure, None if the controller does not measure it
+    pub pressure: Option<u16>,
+    pub phase: TouchPhase,
+}
+
+impl TouchSample {
+    pub fn from_point_event(point: &PointEvent) -> Self {
+        TouchSample {
+            id: point.touch_id,
+            x: point.x,
+            y: point.y,
+            pressure: Some(point.weight as u16),
+            phase: match point.event {
+                EventType::PressDown => TouchPhase::Down,
+                EventType::Contact => TouchPhase::Move,
+                EventType::LiftUp => TouchPhase::Up,
+            },
+        }
+    }
+
+    /// Pressure is the raw Z1 conversion at the configured resolution, not the touch resistance,
+    /// see ResistiveSample::resistance for that. Single touch controller so the id is always 0
+    pub fn from_pen_event(event: &PenEvent) -> Self {
+        let (sample, phase): (&ResistiveSample, TouchPhase) = match event {
+            PenEvent::Down(sample) => (sample, TouchPhase::Down),
+            PenEvent::Move(sample) => (sample, TouchPhase::Move),
+            PenEvent::Up(sample) => (sample, TouchPhase::Up),
+        };
+        TouchSample {
+            id: 0,
+            x: sample.x,
+            y: sample.y,
+            pressure: Some(sample.z1),
+            phase,
+        }
+    }
+}
+
+/// Touch points of one read, unused entries are None
+pub type TouchPoints = [Option<TouchSample>; MAX_TOUCH_POINTS];
+
+/// Points that are still touched, the phase is set by TouchTracker
+pub(crate) fn touched_points_from_touch_data(data: &TouchData) -> TouchPoints {
+    let mut points = [None; MAX_TOUCH_POINTS];
+    let touched = data.points.iter().flatten().filter(|p| p.event != EventType::LiftUp);
+    for (sample, point) in points.iter_mut().zip(touched) {
+        *sample = Some(TouchSample::from_point_event(point));
+    }
+    points
+}
+
+pub(crate) fn touch_points_from_pen_event(event: Option<PenEvent>) -> TouchPoints {
+    let mut points = [None; MAX_TOUCH_POINTS];
+    points[0] = event.as_ref().map(TouchSample::from_pen_event);
+    points
+}
+
+/// Derives the touch phases for controllers that only report the touched points with a track id
+#[derive(Copy, Clone, Debug)]
+pub struct TouchTracker {
+    points: TouchPoints,
+}
+
+impl TouchTracker {
+    pub fn new() -> Self {
+        TouchTracker {
+            points: [None; MAX_TOUCH_POINTS],
+        }
+    }
+
+    fn is_touched(&self, id: u8) -> bool {
+        self.points.iter().flatten().any(|p| p.id == id && p.phase != TouchPhase::Up)
+    }
+
+    /// The phase of touched is ignored, lifted points are appended as Up if there is room
+    pub fn update(&mut self, touched: &TouchPoints) -> TouchPoints {
+        let mut points = [None; MAX_TOUCH_POINTS];
+        for (point, sample) in points.iter_mut().zip(touched.iter().flatten()) {
+            let phase = if self.is_touched(sample.id) { TouchPhase::Move } else { TouchPhase::Down };
+            *point = Some(TouchSample { phase, ..*sample });
+        }
+        for previous in self.points.iter().flatten().filter(|p| p.phase != TouchPhase::Up) {
+            if touched.iter().flatten().any(|p| p.id == previous.id) {
+                continue;
+            }
+            if let Some(point) = points.iter_mut().find(|p| p.is_none()) {
+                *point = Some(TouchSample { phase: TouchPhase::Up, ..*previous });
+            }
+        }
+        self.points = points;
+        points
+    }
+
+    /// Points of the last update that are still touched, for reads without a new report
+    pub fn unchanged(&mut self) -> TouchPoints {
+        let mut points = [None; MAX_TOUCH_POINTS];
+        for (point, sample) in points.iter_mut().zip(self.points.iter().flatten().filter(|p| p.phase != TouchPhase::Up)) {
+            *point = Some(TouchSample { phase: TouchPhase::Move, ..*sample });
+        }
+        self.points = points;
+        points
+    }
+}
+
+impl Default for TouchTracker {
+    fn default() -> Self {
+        TouchTracker::new()
+    }
+}
+
+#[allow(async_fn_in_trait)]
+pub trait TouchController {
+    type Error;
+
+    /// Reads the current touch points, controllers without a touch interrupt have to be read
+    /// periodically to report Up
+    async fn read_touches(&mut self) -> Result<TouchPoints, Self::Error>;
+}
+
+pub trait BlockingTouchController {
+    type Error;
+
+    fn read_touches(&mut self) -> Result<TouchPoints, Self::Error>;
+}
+
+impl<I2C: I2c> TouchController for crate::ft6236_asynch::FT6236<I2C> {
+    type Error = I2C::Error;
+
+    async fn read_touches(&mut self) -> Result<TouchPoints, Self::Error> {
+        let data = self.read_touch_data().await?;
+        Ok(self.tracker.update(&touched_points_from_touch_data(&data)))
+    }
+}
+
+impl<I2C: BlockingI2c> BlockingTouchController for crate::ft6236_blocking::FT6236<I2C> {
+    type Error = I2C::Error;
+
+    fn read_touches(&mut self) -> Result<TouchPoints, Self::Error> {
+        let data = self.read_touch_data()?;
+        Ok(self.tracker.update(&touched_points_from_touch_data(&data)))
+    }
+}
+
+impl<I2C: I2c> TouchController for crate::tsc2007::Tsc2007<I2C> {
+    type Error = I2C::Error;
+
+    async fn read_touches(&mut self) -> Result<TouchPoints, Self::Error> {
+        let event = self.poll().await?;
+        Ok(touch_points_from_pen_event(event))
+    }
+}
+
+impl<I2C: BlockingI2c> BlockingTouchController for crate::tsc2007_blocking::Tsc2007<I2C> {
+    type Error = I2C::Error;
+
+    fn read_touches(&mut self) -> Result<TouchPoints, Self::Error> {
+        let event = self.poll()?;
+        Ok(touch_points_from_pen_event(event))
+    }
+}
+
+impl<SPI: SpiDevice> TouchController for crate::xpt2046::Xpt2046<SPI> {
+    type Error = SPI::Error;
+
+    async fn read_touches(&mut self) -> Result<TouchPoints, Self::Error> {
+        let event = self.poll().await?;
+        Ok(touch_points_from_pen_event(event))
+    }
+}
+
+impl<I2C: I2c> TouchController for crate::gt911::GT911<I2C> {
+    type Error = I2C::Error;
+
+    async fn read_touches(&mut self) -> Result<TouchPoints, Self::Error> {
+        let data = match self.read_touch_data().await? {
+            Some(data) => data,
+            None => return Ok(self.tracker.unchanged()),
+        };
+        let mut touched = [None; MAX_TOUCH_POINTS];
+        for (sample, point) in touched.iter_mut().zip(data.points.iter().flatten()) {
+            *sample = Some(TouchSample {
+                id: point.track_id,
+                x: point.x,
+                y: point.y,
+                pressure: Some(point.size),
+                phase: TouchPhase::Move,
+            });
+        }
+        Ok(self.tracker.update(&touched))
+    }
+}
+
+impl<I2C: I2c> TouchController for crate::cst816::CST816<I2C> {
+    type Error = I2C::Error;
+
+    async fn read_touches(&mut self) -> Result<TouchPoints, Self::Error> {
+        let data = self.read_touch_data().await?;
+        let mut touched = [None; MAX_TOUCH_POINTS];
+        touched[0] = data.point.filter(|point| point.event != EventType::LiftUp).map(|point| TouchSample {
+            id: 0,
+            x: point.x,
+            y: point.y,
+            pressure: None,
+            phase: TouchPhase::Move,
+        });
+        Ok(self.tracker.update(&touched))
+    }
+}
+
+/// Maps the raw positions of a controller to display coordinates,
+/// implements the controller traits itself so adapters can be chained
+pub struct MappedTouchController<T> {
+    controller: T,
+    mapper: TouchPosMapper,
+    orientation: Orientation,
+}
+
+impl<T> MappedTouchController<T> {
+    pub fn new(controller: T, mapper: TouchPosMapper, orientation: Orientation) -> Self {
+        MappedTouchController {
+            controller,
+            mapper,
+            orientation,
+        }
+    }
+
+    pub fn controller(&mut self) -> &mut T {
+        &mut self.controller
+    }
+
+    pub fn release(self) -> T {
+        self.controller
+    }
+
+    pub fn set_orientation(&mut self, orientation: Orientation) {
+        self.orientation = orientation;
+    }
+
+    fn map(&self, mut points: TouchPoints) -> TouchPoints {
+        for sample in points.iter_mut().flatten() {
+            let pos = self.mapper.map_touch_pos(sample.x, sample.y, self.orientation);
+            sample.x = pos.x as u16;
+            sample.y = pos.y as u16;
+        }
+        points
+    }
+}
+
+impl<T: TouchController> TouchController for MappedTouchController<T> {
+    type Error = T::Error;
+
+    async fn read_touches(&mut self) -> Result<TouchPoints, Self::Error> {
+        let points = self.controller.read_touches().await?;
+        Ok(self.map(points))
+    }
+}
+
+impl<T: BlockingTouchController> BlockingTouchController for MappedTouchController<T> {
+    type Error = T::Error;
+
+    fn read_touches(&mut self) -> Result<TouchPoints, Self::Error> {
+        let points = self.controller.read_touches()?;
+        Ok(self.map(points))
+    }
+}
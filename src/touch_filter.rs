@@ -0,0 +1,561 @@
+//! Filters between touch driver and TouchPosMapper. Every filter handles the samples of one
+//! touch point, filters are combined with chain and run on a controller with FilteredTouchController
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+
+use crate::touch::{TouchController, TouchPhase, TouchPoints, TouchSample};
+
+pub const MAX_FILTER_WINDOW: usize = 8;
+
+pub trait TouchFilter {
+    /// None if the sample is dropped or held back, an Up sample always resets the filter
+    fn update(&mut self, sample: TouchSample, time_ms: u64) -> Option<TouchSample>;
+
+    fn reset(&mut self);
+
+    fn chain<F: TouchFilter>(self, next: F) -> Chain<Self, F>
+        where Self: Sized {
+        Chain {
+            first: self,
+            second: next,
+        }
+    }
+}
+
+/// Runs a filter over a recorded stream of (time_ms, sample), returns the filter output
+pub fn apply_to_stream<F: TouchFilter>(filter: &mut F, samples: &[(u64, TouchSample)]) -> Vec<TouchSample> {
+    filter.reset();
+    samples.iter()
+        .filter_map(|(time_ms, sample)| filter.update(*sample, *time_ms))
+        .collect()
+}
+
+fn with_pos(sample: TouchSample, x: f32, y: f32) -> TouchSample {
+    TouchSample {
+        x: if x < 0.0 { 0 } else { (x + 0.5) as u16 },
+        y: if y < 0.0 { 0 } else { (y + 0.5) as u16 },
+        ..sample
+    }
+}
+
+fn abs(value: f32) -> f32 {
+    if value < 0.0 { -value } else { value }
+}
+
+pub struct Chain<A, B> {
+    first: A,
+    second: B,
+}
+
+impl<A: TouchFilter, B: TouchFilter> TouchFilter for Chain<A, B> {
+    fn update(&mut self, sample: TouchSample, time_ms: u64) -> Option<TouchSample> {
+        let sample = self.first.update(sample, time_ms)?;
+        self.second.update(sample, time_ms)
+    }
+
+    fn reset(&mut self) {
+        self.first.reset();
+        self.second.reset();
+    }
+}
+
+/// Drops moves closer than radius to the last reported position
+pub struct DeadZone {
+    radius: u32,
+    last: Option<TouchSample>,
+}
+
+impl DeadZone {
+    pub fn new(radius: u32) -> Self {
+        DeadZone {
+            radius,
+            last: None,
+        }
+    }
+}
+
+impl TouchFilter for DeadZone {
+    fn update(&mut self, sample: TouchSample, _time_ms: u64) -> Option<TouchSample> {
+        match (sample.phase, self.last) {
+            (TouchPhase::Move, Some(last)) => {
+                let dx = sample.x.abs_diff(last.x) as u64;
+                let dy = sample.y.abs_diff(last.y) as u64;
+                let radius = self.radius as u64;
+                if dx * dx + dy * dy < radius * radius {
+                    return None;
+                }
+                self.last = Some(sample);
+                Some(sample)
+            }
+            (TouchPhase::Up, Some(last)) => {
+                self.last = None;
+                Some(TouchSample { phase: TouchPhase::Up, ..last })
+            }
+            (TouchPhase::Up, None) => Some(sample),
+            _ => {
+                self.last = Some(sample);
+                Some(sample)
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        self.last = None;
+    }
+}
+
+/// Average over the last window samples of the touch
+pub struct MovingAverage {
+    window: usize,
+    positions: [(u16, u16); MAX_FILTER_WINDOW],
+    count: usize,
+    next: usize,
+}
+
+impl MovingAverage {
+    /// window is limited to 1..=MAX_FILTER_WINDOW
+    pub fn new(window: usize) -> Self {
+        MovingAverage {
+            window: window.clamp(1, MAX_FILTER_WINDOW),
+            positions: [(0, 0); MAX_FILTER_WINDOW],
+            count: 0,
+            next: 0,
+        }
+    }
+
+    fn average(&self) -> (f32, f32) {
+        let sum = self.positions[..self.count].iter()
+            .fold((0u32, 0u32), |sum, p| (sum.0 + p.0 as u32, sum.1 + p.1 as u32));
+        (sum.0 as f32 / self.count as f32, sum.1 as f32 / self.count as f32)
+    }
+}
+
+impl TouchFilter for MovingAverage {
+    fn update(&mut self, sample: TouchSample, _time_ms: u64) -> Option<TouchSample> {
+        if sample.phase == TouchPhase::Down {
+            self.reset();
+        }
+        if sample.phase != TouchPhase::Up || self.count == 0 {
+            self.positions[self.next] = (sample.x, sample.y);
+            self.next = (self.next + 1) % self.window;
+            self.count = (self.count + 1).min(self.window);
+        }
+        let (x, y) = self.average();
+        if sample.phase == TouchPhase::Up {
+            self.reset();
+        }
+        Some(with_pos(sample, x, y))
+    }
+
+    fn reset(&mut self) {
+        self.count = 0;
+        self.next = 0;
+    }
+}
+
+/// s = s + alpha * (x - s), alpha 0..=1, smaller values smooth more
+pub struct ExponentialSmoothing {
+    alpha: f32,
+    state: Option<(f32, f32)>,
+}
+
+impl ExponentialSmoothing {
+    pub fn new(alpha: f32) -> Self {
+        ExponentialSmoothing {
+            alpha: alpha.clamp(0.0, 1.0),
+            state: None,
+        }
+    }
+}
+
+impl TouchFilter for ExponentialSmoothing {
+    fn update(&mut self, sample: TouchSample, _time_ms: u64) -> Option<TouchSample> {
+        let (x, y) = match (sample.phase, self.state) {
+            (TouchPhase::Move, Some((x, y))) => {
+                (x + self.alpha * (sample.x as f32 - x), y + self.alpha * (sample.y as f32 - y))
+            }
+            // the lift up position is often off, keep the smoothed one
+            (TouchPhase::Up, Some(state)) => state,
+            _ => (sample.x as f32, sample.y as f32),
+        };
+        self.state = if sample.phase == TouchPhase::Up { None } else { Some((x, y)) };
+        Some(with_pos(sample, x, y))
+    }
+
+    fn reset(&mut self) {
+        self.state = None;
+    }
+}
+
+/// Holds back the last drop_count moves and discards them on lift up, the panel
+/// reports wrong positions while the pressure drops. Delays moves by drop_count samples
+pub struct ReleaseGlitchFilter {
+    drop_count: usize,
+    pending: [Option<TouchSample>; MAX_FILTER_WINDOW],
+    count: usize,
+    last: Option<TouchSample>,
+}
+
+impl ReleaseGlitchFilter {
+    /// drop_count is limited to 0..=MAX_FILTER_WINDOW
+    pub fn new(drop_count: usize) -> Self {
+        ReleaseGlitchFilter {
+            drop_count: drop_count.min(MAX_FILTER_WINDOW),
+            pending: [None; MAX_FILTER_WINDOW],
+            count: 0,
+            last: None,
+        }
+    }
+}
+
+impl TouchFilter for ReleaseGlitchFilter {
+    fn update(&mut self, sample: TouchSample, _time_ms: u64) -> Option<TouchSample> {
+        match sample.phase {
+            TouchPhase::Down => {
+                self.reset();
+                self.last = Some(sample);
+                Some(sample)
+            }
+            TouchPhase::Move => {
+                if self.drop_count == 0 {
+                    self.last = Some(sample);
+                    return Some(sample);
+                }
+                if self.count < self.drop_count {
+                    self.pending[self.count] = Some(sample);
+                    self.count += 1;
+                    return None;
+                }
+                let oldest = self.pending[0];
+                self.pending.copy_within(1..self.drop_count, 0);
+                self.pending[self.drop_count - 1] = Some(sample);
+                self.last = oldest;
+                oldest
+            }
+            TouchPhase::Up => {
+                let up = match self.last {
+                    Some(last) => TouchSample { phase: TouchPhase::Up, ..last },
+                    None => sample,
+                };
+                self.reset();
+                Some(up)
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        self.pending = [None; MAX_FILTER_WINDOW];
+        self.count = 0;
+        self.last = None;
+    }
+}
+
+/// Low pass with a first order filter, alpha from cutoff frequency and sample interval
+fn smoothing_factor(cutoff_hz: f32, interval_s: f32) -> f32 {
+    let tau = 1.0 / (2.0 * core::f32::consts::PI * cutoff_hz);
+    1.0 / (1.0 + tau / interval_s)
+}
+
+#[derive(Copy, Clone, Debug)]
+struct OneEuroAxis {
+    value: f32,
+    derivative: f32,
+}
+
+/// 1€ filter (Casiez et al.), smooths slow movements strongly and follows fast ones.
+/// min_cutoff in Hz sets the jitter reduction at rest, beta the speed adaption
+pub struct OneEuroFilter {
+    min_cutoff: f32,
+    beta: f32,
+    derivative_cutoff: f32,
+    state: Option<([OneEuroAxis; 2], u64)>,
+}
+
+impl OneEuroFilter {
+    pub fn new(min_cutoff: f32, beta: f32) -> Self {
+        OneEuroFilter {
+            min_cutoff,
+            beta,
+            derivative_cutoff: 1.0,
+            state: None,
+        }
+    }
+
+    pub fn set_derivative_cutoff(&mut self, derivative_cutoff: f32) {
+        self.derivative_cutoff = derivative_cutoff;
+    }
+
+    fn filter_axis(&self, axis: OneEuroAxis, value: f32, interval_s: f32) -> OneEuroAxis {
+        let raw_derivative = (value - axis.value) / interval_s;
+        let derivative_alpha = smoothing_factor(self.derivative_cutoff, interval_s);
+        let derivative = axis.derivative + derivative_alpha * (raw_derivative - axis.derivative);
+        let cutoff = self.min_cutoff + self.beta * abs(derivative);
+        let alpha = smoothing_factor(cutoff, interval_s);
+        OneEuroAxis {
+            value: axis.value + alpha * (value - axis.value),
+            derivative,
+        }
+    }
+}
+
+impl TouchFilter for OneEuroFilter {
+    fn update(&mut self, sample: TouchSample, time_ms: u64) -> Option<TouchSample> {
+        let axes = match (sample.phase, self.state) {
+            (TouchPhase::Move, Some((axes, last_ms))) => {
+                if time_ms <= last_ms {
+                    return Some(with_pos(sample, axes[0].value, axes[1].value));
+                }
+                let interval_s = (time_ms - last_ms) as f32 / 1000.0;
+                [self.filter_axis(axes[0], sample.x as f32, interval_s),
+                    self.filter_axis(axes[1], sample.y as f32, interval_s)]
+            }
+            (TouchPhase::Up, Some((axes, _))) => axes,
+            _ => [OneEuroAxis { value: sample.x as f32, derivative: 0.0 },
+                OneEuroAxis { value: sample.y as f32, derivative: 0.0 }],
+        };
+        self.state = if sample.phase == TouchPhase::Up { None } else { Some((axes, time_ms)) };
+        Some(with_pos(sample, axes[0].value, axes[1].value))
+    }
+
+    fn reset(&mut self) {
+        self.state = None;
+    }
+}
+
+/// Filters the first touch point of a controller, the other points are passed unchanged.
+/// clock returns the current time in milliseconds. While the finger is down a sample
+/// dropped by the filter is reported as a move to the last filtered position, and the
+/// filter is reset when the first point changes to another touch id
+pub struct FilteredTouchController<T, F, C> {
+    controller: T,
+    filter: F,
+    clock: C,
+    last: Option<TouchSample>,
+}
+
+impl<T, F, C> FilteredTouchController<T, F, C>
+    where F: TouchFilter, C: FnMut() -> u64 {
+    pub fn new(controller: T, filter: F, clock: C) -> Self {
+        FilteredTouchController {
+            controller,
+            filter,
+            clock,
+            last: None,
+        }
+    }
+
+    pub fn controller(&mut self) -> &mut T {
+        &mut self.controller
+    }
+
+    pub fn filter(&mut self) -> &mut F {
+        &mut self.filter
+    }
+
+    pub fn release(self) -> T {
+        self.controller
+    }
+
+    fn filter_point(&mut self, point: Option<TouchSample>) -> Option<TouchSample> {
+        let sample = match point {
+            Some(sample) => sample,
+            None => {
+                // the controller did not report Up, don't carry the state to the next touch
+                if self.last.take().is_some() {
+                    self.filter.reset();
+                }
+                return None;
+            }
+        };
+        if self.last.is_some_and(|last| last.id != sample.id) {
+            self.filter.reset();
+            self.last = None;
+        }
+        let time_ms = (self.clock)();
+        match self.filter.update(sample, time_ms) {
+            Some(filtered) => {
+                self.last = if filtered.phase == TouchPhase::Up { None } else { Some(filtered) };
+                Some(filtered)
+            }
+            None if sample.phase != TouchPhase::Up => {
+                self.last.map(|last| TouchSample { phase: TouchPhase::Move, ..last })
+            }
+            None => {
+                self.last = None;
+                None
+            }
+        }
+    }
+}
+
+impl<T, F, C> TouchController for FilteredTouchController<T, F, C>
+    where T: TouchController, F: TouchFilter, C: FnMut() -> u64 {
+    type Error = T::Error;
+
+    async fn read_touches(&mut self) -> Result<TouchPoints, Self::Error> {
+        let mut points = self.controller.read_touches().await?;
+        points[0] = self.filter_point(points[0]);
+        Ok(points)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(phase: TouchPhase, x: u16, y: u16) -> TouchSample {
+        TouchSample {
+            id: 0,
+            x,
+            y,
+            pressure: None,
+            phase,
+        }
+    }
+
+    fn positions(samples: &[TouchSample]) -> Vec<(TouchPhase, u16, u16)> {
+        samples.iter().map(|s| (s.phase, s.x, s.y)).collect()
+    }
+
+    #[test]
+    fn dead_zone_drops_moves_inside_radius() {
+        let stream = [
+            (0, sample(TouchPhase::Down, 100, 100)),
+            (10, sample(TouchPhase::Move, 102, 101)),
+            (20, sample(TouchPhase::Move, 110, 100)),
+            (30, sample(TouchPhase::Move, 111, 102)),
+            (40, sample(TouchPhase::Up, 112, 102)),
+        ];
+        let output = apply_to_stream(&mut DeadZone::new(5), &stream);
+        assert_eq!(positions(&output), [(TouchPhase::Down, 100, 100), (TouchPhase::Move, 110, 100),
+            (TouchPhase::Up, 110, 100)]);
+    }
+
+    #[test]
+    fn moving_average_resets_on_down() {
+        let stream = [
+            (0, sample(TouchPhase::Down, 0, 0)),
+            (10, sample(TouchPhase::Move, 10, 20)),
+            (20, sample(TouchPhase::Down, 100, 100)),
+            (30, sample(TouchPhase::Move, 110, 100)),
+        ];
+        let output = apply_to_stream(&mut MovingAverage::new(4), &stream);
+        assert_eq!(positions(&output), [(TouchPhase::Down, 0, 0), (TouchPhase::Move, 5, 10),
+            (TouchPhase::Down, 100, 100), (TouchPhase::Move, 105, 100)]);
+    }
+
+    #[test]
+    fn exponential_smoothing_keeps_smoothed_position_on_up() {
+        let stream = [
+            (0, sample(TouchPhase::Down, 0, 0)),
+            (10, sample(TouchPhase::Move, 100, 40)),
+            (20, sample(TouchPhase::Up, 200, 200)),
+            (30, sample(TouchPhase::Down, 10, 10)),
+        ];
+        let output = apply_to_stream(&mut ExponentialSmoothing::new(0.5), &stream);
+        assert_eq!(positions(&output), [(TouchPhase::Down, 0, 0), (TouchPhase::Move, 50, 20),
+            (TouchPhase::Up, 50, 20), (TouchPhase::Down, 10, 10)]);
+    }
+
+    #[test]
+    fn release_glitch_filter_drops_the_last_moves_before_up() {
+        let stream = [
+            (0, sample(TouchPhase::Down, 0, 0)),
+            (10, sample(TouchPhase::Move, 10, 0)),
+            (20, sample(TouchPhase::Move, 20, 0)),
+            (30, sample(TouchPhase::Move, 30, 0)),
+            (40, sample(TouchPhase::Move, 40, 0)),
+            (50, sample(TouchPhase::Up, 45, 0)),
+        ];
+        let output = apply_to_stream(&mut ReleaseGlitchFilter::new(2), &stream);
+        assert_eq!(positions(&output), [(TouchPhase::Down, 0, 0), (TouchPhase::Move, 10, 0),
+            (TouchPhase::Move, 20, 0), (TouchPhase::Up, 20, 0)]);
+
+        let output = apply_to_stream(&mut ReleaseGlitchFilter::new(0), &stream);
+        assert_eq!(output.len(), stream.len());
+    }
+
+    #[test]
+    fn one_euro_filter_holds_position_if_time_does_not_advance() {
+        let stream = [
+            (100, sample(TouchPhase::Down, 0, 0)),
+            (110, sample(TouchPhase::Move, 20, 20)),
+            (110, sample(TouchPhase::Move, 200, 200)),
+            (105, sample(TouchPhase::Move, 300, 300)),
+            (120, sample(TouchPhase::Move, 20, 20)),
+            (130, sample(TouchPhase::Up, 300, 300)),
+        ];
+        let output = apply_to_stream(&mut OneEuroFilter::new(1.0, 0.01), &stream);
+        assert_eq!(output.len(), stream.len());
+        let held = (output[1].x, output[1].y);
+        assert!(held.0 > 0 && held.0 < 20);
+        assert_eq!((output[2].x, output[2].y), held);
+        assert_eq!((output[3].x, output[3].y), held);
+        assert!(output[4].x >= held.0 && output[4].x <= 20);
+        assert_eq!((output[5].phase, output[5].x), (TouchPhase::Up, output[4].x));
+    }
+
+    fn read_all<F: TouchFilter>(filter: F, samples: &[(u64, TouchSample)]) -> Vec<Option<(TouchPhase, u8, u16, u16)>> {
+        let replay = crate::touch_record::TouchReplay::new(samples.to_vec());
+        let mut time_ms = 0;
+        let mut controller = FilteredTouchController::new(replay, filter, move || {
+            time_ms += 10;
+            time_ms
+        });
+        let mut reads = Vec::new();
+        while !controller.controller().is_finished() {
+            let points = crate::mock_i2c::block_on(controller.read_touches()).unwrap();
+            reads.push(points[0].map(|s| (s.phase, s.id, s.x, s.y)));
+        }
+        reads
+    }
+
+    #[test]
+    fn controller_reports_dropped_samples_as_held_position() {
+        let stream = [
+            (0, sample(TouchPhase::Down, 100, 100)),
+            (10, sample(TouchPhase::Move, 101, 100)),
+            (20, sample(TouchPhase::Move, 102, 101)),
+            (30, sample(TouchPhase::Move, 110, 100)),
+            (40, sample(TouchPhase::Up, 111, 100)),
+        ];
+        assert_eq!(read_all(DeadZone::new(5), &stream), [
+            Some((TouchPhase::Down, 0, 100, 100)),
+            Some((TouchPhase::Move, 0, 100, 100)),
+            Some((TouchPhase::Move, 0, 100, 100)),
+            Some((TouchPhase::Move, 0, 110, 100)),
+            Some((TouchPhase::Up, 0, 110, 100)),
+        ]);
+
+        let stream = [
+            (0, sample(TouchPhase::Down, 0, 0)),
+            (10, sample(TouchPhase::Move, 10, 0)),
+            (20, sample(TouchPhase::Move, 20, 0)),
+            (30, sample(TouchPhase::Up, 25, 0)),
+        ];
+        assert_eq!(read_all(ReleaseGlitchFilter::new(2), &stream), [
+            Some((TouchPhase::Down, 0, 0, 0)),
+            Some((TouchPhase::Move, 0, 0, 0)),
+            Some((TouchPhase::Move, 0, 0, 0)),
+            Some((TouchPhase::Up, 0, 0, 0)),
+        ]);
+    }
+
+    #[test]
+    fn controller_resets_the_filter_when_the_finger_changes() {
+        let stream = [
+            (0, sample(TouchPhase::Down, 0, 0)),
+            (10, sample(TouchPhase::Move, 100, 0)),
+            (20, TouchSample { id: 1, ..sample(TouchPhase::Move, 200, 200) }),
+            (30, TouchSample { id: 1, ..sample(TouchPhase::Move, 220, 200) }),
+        ];
+        assert_eq!(read_all(ExponentialSmoothing::new(0.5), &stream), [
+            Some((TouchPhase::Down, 0, 0, 0)),
+            Some((TouchPhase::Move, 0, 50, 0)),
+            Some((TouchPhase::Move, 1, 200, 200)),
+            Some((TouchPhase::Move, 1, 210, 200)),
+        ]);
+    }
+}
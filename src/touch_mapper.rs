@@ -1,42 +1,246 @@
+use embedded_graphics::geometry::Point;
+
+/// Display rotation, clockwise from the native orientation of the panel
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug, Default)]
+pub enum Orientation {
+    #[default]
+    Deg0,
+    Deg90,
+    Deg180,
+    Deg270,
+}
+
+impl Orientation {
+    /// 0..=3 as the rotation in quarter turns, None for other values
+    pub fn from_u8(orientation: u8) -> Option<Self> {
+        match orientation {
+            0 => Some(Orientation::Deg0),
+            1 => Some(Orientation::Deg90),
+            2 => Some(Orientation::Deg180),
+            3 => Some(Orientation::Deg270),
+            _ => None,
+        }
+    }
+
+    pub fn as_u8(&self) -> u8 {
+        match self {
+            Orientation::Deg0 => 0,
+            Orientation::Deg90 => 1,
+            Orientation::Deg180 => 2,
+            Orientation::Deg270 => 3,
+        }
+    }
+
+    pub fn get_transform(&self) -> TouchTransform {
+        match self {
+            Orientation::Deg0 => TouchTransform { swap_xy: false, mirror_x: false, mirror_y: false },
+            Orientation::Deg90 => TouchTransform { swap_xy: true, mirror_x: false, mirror_y: true },
+            Orientation::Deg180 => TouchTransform { swap_xy: false, mirror_x: true, mirror_y: true },
+            Orientation::Deg270 => TouchTransform { swap_xy: true, mirror_x: true, mirror_y: false },
+        }
+    }
+}
+
+/// Panel orientation as display drivers describe it (MADCTL MV, MX and MY).
+/// Swap is applied first, mirroring is in the swapped coordinates
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug, Default)]
+pub struct TouchTransform {
+    pub swap_xy: bool,
+    pub mirror_x: bool,
+    pub mirror_y: bool,
+}
+
+/// Affine transformation from raw touch to display coordinates,
+/// display x = a * x + b * y + c, display y = d * x + e * y + f
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct CalibrationMatrix {
+    pub a: f32,
+    pub b: f32,
+    pub c: f32,
+    pub d: f32,
+    pub e: f32,
+    pub f: f32,
+}
+
+impl CalibrationMatrix {
+    /// Exact solution for three raw samples and their display targets,
+    /// None if the points are on one line
+    pub fn from_three_points(raw: &[(u16, u16); 3], display: &[(u16, u16); 3]) -> Option<Self> {
+        CalibrationMatrix::from_points(raw, display)
+    }
+
+    /// Least squares fit for three or more raw samples and their display targets,
+    /// e.g. four corners and the center. None if there are less than three points,
+    /// the slices differ in length or all points are on one line
+    pub fn from_points(raw: &[(u16, u16)], display: &[(u16, u16)]) -> Option<Self> {
+        if raw.len() < 3 || raw.len() != display.len() {
+            return None;
+        }
+        // normal equations of the least squares problem, the same for both axes
+        let mut m = [[0f64; 3]; 3];
+        let mut rhs_x = [0f64; 3];
+        let mut rhs_y = [0f64; 3];
+        for (r, d) in raw.iter().zip(display) {
+            let v = [r.0 as f64, r.1 as f64, 1.0];
+            for row in 0..3 {
+                for col in 0..3 {
+                    m[row][col] += v[row] * v[col];
+                }
+                rhs_x[row] += v[row] * d.0 as f64;
+                rhs_y[row] += v[row] * d.1 as f64;
+            }
+        }
+        let (a, b, c) = solve3(&m, &rhs_x)?;
+        let (d, e, f) = solve3(&m, &rhs_y)?;
+        Some(CalibrationMatrix {
+            a: a as f32,
+            b: b as f32,
+            c: c as f32,
+            d: d as f32,
+            e: e as f32,
+            f: f as f32,
+        })
+    }
+
+    /// All coefficients are finite and the matrix can be inverted, false for corrupted stored data
+    pub fn is_valid(&self) -> bool {
+        let values = [self.a, self.b, self.c, self.d, self.e, self.f];
+        let det = self.a * self.e - self.b * self.d;
+        values.iter().all(|v| v.is_finite()) && det.is_finite() && det != 0.0
+    }
+
+    pub fn apply(&self, x: u16, y: u16) -> (f32, f32) {
+        let (x, y) = (x as f32, y as f32);
+        (self.a * x + self.b * y + self.c, self.d * x + self.e * y + self.f)
+    }
+}
+
+fn det3(m: &[[f64; 3]; 3]) -> f64 {
+    m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+        - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+        + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+}
+
+/// Cramer's rule, None for a singular matrix
+fn solve3(m: &[[f64; 3]; 3], rhs: &[f64; 3]) -> Option<(f64, f64, f64)> {
+    let det = det3(m);
+    if det > -1e-9 && det < 1e-9 {
+        return None;
+    }
+    let mut result = [0f64; 3];
+    for (col, value) in result.iter_mut().enumerate() {
+        let mut replaced = *m;
+        for row in 0..3 {
+            replaced[row][col] = rhs[row];
+        }
+        *value = det3(&replaced) / det;
+    }
+    Some((result[0], result[1], result[2]))
+}
+
+#[derive(Clone, Copy, Debug)]
+enum TouchMapping {
+    /// per axis scale and offset from the touch limits
+    Linear {
+        scale: (f32, f32),
+        offset: (f32, f32),
+    },
+    Calibrated(CalibrationMatrix),
+}
 
 #[derive(Clone, Copy, Debug)]
 pub struct TouchPosMapper {
     width: u16,
     height: u16,
-    touch_limit_x: (u16, u16),
-    touch_limit_y: (u16, u16),
-    scale: (f32, f32),
-    offset: (f32, f32),
+    mapping: TouchMapping,
 }
 
 impl TouchPosMapper {
+    /// Fixed limits, e.g. TS_MINX..TS_MAXX of the driver.
+    ///
+    /// # Panics
+    ///
+    /// If a limit pair is not increasing. Use try_new for limits loaded from settings
     pub fn new(width: u16, height: u16, touch_limit_x: (u16, u16), touch_limit_y: (u16, u16)) -> Self {
+        TouchPosMapper::try_new(width, height, touch_limit_x, touch_limit_y)
+            .expect("touch limits must be (min, max) with min < max")
+    }
+
+    /// None if touch_limit_x.1 <= touch_limit_x.0 or touch_limit_y.1 <= touch_limit_y.0
+    pub fn try_new(width: u16, height: u16, touch_limit_x: (u16, u16), touch_limit_y: (u16, u16)) -> Option<Self> {
+        if touch_limit_x.1 <= touch_limit_x.0 || touch_limit_y.1 <= touch_limit_y.0 {
+            return None;
+        }
         let scale_x = width as f32 / (touch_limit_x.1 - touch_limit_x.0) as f32;
-        let offset_x = -(touch_limit_x.0 as f32) * width as f32 / (touch_limit_x.1 - touch_limit_x.0) as f32;
+        let offset_x = -(touch_limit_x.0 as f32) * scale_x;
 
         let scale_y = height as f32 / (touch_limit_y.1 - touch_limit_y.0) as f32;
-        let offset_y = -(touch_limit_y.0 as f32) * height as f32 / (touch_limit_y.1 - touch_limit_y.0) as f32;
+        let offset_y = -(touch_limit_y.0 as f32) * scale_y;
 
+        Some(TouchPosMapper {
+            width,
+            height,
+            mapping: TouchMapping::Linear {
+                scale: (scale_x, scale_y),
+                offset: (offset_x, offset_y),
+            },
+        })
+    }
+
+    /// Uses the calibration matrix instead of the per axis limits, corrects rotation and skew
+    pub fn new_calibrated(width: u16, height: u16, calibration: CalibrationMatrix) -> Self {
         TouchPosMapper {
             width,
             height,
-            touch_limit_x,
-            touch_limit_y,
-            scale: (scale_x, scale_y),
-            offset: (offset_x, offset_y),
+            mapping: TouchMapping::Calibrated(calibration),
         }
     }
 
-    pub fn map_touch_pos(self, x: u16, y: u16, width: u16, height: u16, orientation: u8) -> (u16, u16) {
-        let x_scaled = ((x as f32 * self.scale.0 + self.offset.0) as u16).min(self.width);
-        let y_scaled = ((y as f32 * self.scale.1 + self.offset.1) as u16).min(self.height);
+    /// None if the calibration matrix is not valid, see CalibrationMatrix::is_valid
+    pub fn try_new_calibrated(width: u16, height: u16, calibration: CalibrationMatrix) -> Option<Self> {
+        if !calibration.is_valid() {
+            return None;
+        }
+        Some(TouchPosMapper::new_calibrated(width, height, calibration))
+    }
 
-        match orientation {
-            0 => (x_scaled, y_scaled),
-            1 => (y_scaled, height - x_scaled),
-            2 => (width - x_scaled, height - y_scaled),
-            3 => (width - y_scaled, x_scaled),
-            _ => (x, y)
+    pub fn get_calibration(&self) -> Option<CalibrationMatrix> {
+        match self.mapping {
+            TouchMapping::Calibrated(calibration) => Some(calibration),
+            TouchMapping::Linear { .. } => None,
+        }
+    }
+
+    /// Position in the native orientation, clamped to 0..width and 0..height
+    fn scale_touch_pos(&self, x: u16, y: u16) -> (i32, i32) {
+        let (x_scaled, y_scaled) = match self.mapping {
+            TouchMapping::Calibrated(calibration) => calibration.apply(x, y),
+            TouchMapping::Linear { scale, offset } => (x as f32 * scale.0 + offset.0, y as f32 * scale.1 + offset.1),
+        };
+        let max_x = (self.width as i32 - 1).max(0);
+        let max_y = (self.height as i32 - 1).max(0);
+        ((x_scaled as i32).clamp(0, max_x), (y_scaled as i32).clamp(0, max_y))
+    }
+
+    /// width and height are the native panel size given to the constructor,
+    /// they are swapped for 90 and 270 degrees
+    pub fn map_touch_pos(&self, x: u16, y: u16, orientation: Orientation) -> Point {
+        self.map_touch_pos_with_transform(x, y, orientation.get_transform())
+    }
+
+    pub fn map_touch_pos_with_transform(&self, x: u16, y: u16, transform: TouchTransform) -> Point {
+        let (mut x, mut y) = self.scale_touch_pos(x, y);
+        let (mut width, mut height) = (self.width as i32, self.height as i32);
+        if transform.swap_xy {
+            (x, y) = (y, x);
+            (width, height) = (height, width);
         }
+        if transform.mirror_x {
+            x = (width - 1 - x).max(0);
+        }
+        if transform.mirror_y {
+            y = (height - 1 - y).max(0);
+        }
+        Point::new(x, y)
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,440 @@
+//! Recording of touch samples to the SD card and replay through the TouchController traits.
+//!
+//! A recording is a header followed by one fixed size record per touch sample, the samples
+//! of one read share the read counter and are replayed together.
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use core::convert::Infallible;
+
+use embedded_sdmmc::{BlockDevice, RawFile};
+use embedded_sdmmc::filesystem::ToShortFileName;
+
+use crate::sdcard::{SdcardError, SdcardManager};
+use crate::touch::{BlockingTouchController, MAX_TOUCH_POINTS, TouchController, TouchPhase, TouchPoints, TouchSample};
+
+const RECORDING_MAGIC: [u8; 4] = *b"ETRC";
+pub const RECORDING_VERSION: u16 = 1;
+const HEADER_LEN: usize = 6;
+/// time_ms, read, id, phase, x, y and pressure
+const RECORD_LEN: usize = 8 + 4 + 1 + 1 + 2 + 2 + 2;
+const NO_PRESSURE: u16 = 0xFFFF;
+
+fn encode_sample(time_ms: u64, read: u32, sample: &TouchSample) -> [u8; RECORD_LEN] {
+    let mut record = [0u8; RECORD_LEN];
+    record[0..8].copy_from_slice(&time_ms.to_le_bytes());
+    record[8..12].copy_from_slice(&read.to_le_bytes());
+    record[12] = sample.id;
+    record[13] = match sample.phase {
+        TouchPhase::Down => 0,
+        TouchPhase::Move => 1,
+        TouchPhase::Up => 2,
+    };
+    record[14..16].copy_from_slice(&sample.x.to_le_bytes());
+    record[16..18].copy_from_slice(&sample.y.to_le_bytes());
+    record[18..20].copy_from_slice(&sample.pressure.unwrap_or(NO_PRESSURE).to_le_bytes());
+    record
+}
+
+fn decode_sample(record: &[u8]) -> Option<(u64, u32, TouchSample)> {
+    let mut time_ms = [0u8; 8];
+    time_ms.copy_from_slice(&record[0..8]);
+    let read = u32::from_le_bytes([record[8], record[9], record[10], record[11]]);
+    let phase = match record[13] {
+        0 => TouchPhase::Down,
+        1 => TouchPhase::Move,
+        2 => TouchPhase::Up,
+        _ => return None,
+    };
+    let pressure = u16::from_le_bytes([record[18], record[19]]);
+    Some((u64::from_le_bytes(time_ms), read, TouchSample {
+        id: record[12],
+        x: u16::from_le_bytes([record[14], record[15]]),
+        y: u16::from_le_bytes([record[16], record[17]]),
+        pressure: if pressure == NO_PRESSURE { None } else { Some(pressure) },
+        phase,
+    }))
+}
+
+/// Read counter for samples without one, a read ends when the timestamp changes,
+/// a touch id repeats or it has MAX_TOUCH_POINTS samples
+fn count_reads(samples: &[(u64, TouchSample)]) -> Vec<u32> {
+    let mut reads = Vec::with_capacity(samples.len());
+    let mut read = 0u32;
+    let mut read_start = 0;
+    for (index, (time_ms, sample)) in samples.iter().enumerate() {
+        if index > 0 {
+            let same_time = samples[index - 1].0 == *time_ms;
+            let repeated_id = samples[read_start..index].iter().any(|(_, s)| s.id == sample.id);
+            if !same_time || repeated_id || index - read_start == MAX_TOUCH_POINTS {
+                read = read.wrapping_add(1);
+                read_start = index;
+            }
+        }
+        reads.push(read);
+    }
+    reads
+}
+
+/// Encodes the samples of one read into buffer, returns the number of bytes used
+fn encode_points(time_ms: u64, read: u32, points: &TouchPoints,
+                 buffer: &mut [u8; RECORD_LEN * MAX_TOUCH_POINTS]) -> usize {
+    let mut len = 0;
+    for sample in points.iter().flatten() {
+        buffer[len..len + RECORD_LEN].copy_from_slice(&encode_sample(time_ms, read, sample));
+        len += RECORD_LEN;
+    }
+    len
+}
+
+/// Writes touch samples to a file in the root directory, the root directory must be open
+pub struct TouchRecorder {
+    file: Option<RawFile>,
+    sample_count: usize,
+    read_count: u32,
+}
+
+impl TouchRecorder {
+    pub fn new() -> Self {
+        TouchRecorder {
+            file: None,
+            sample_count: 0,
+            read_count: 0,
+        }
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.file.is_some()
+    }
+
+    pub fn get_sample_count(&self) -> usize {
+        self.sample_count
+    }
+
+    /// Creates or truncates the file and writes the header
+    pub fn start<D, N>(&mut self, sdcard: &mut SdcardManager<D>, name: N) -> Result<(), SdcardError<D::Error>>
+        where D: BlockDevice, N: ToShortFileName {
+        self.stop(sdcard)?;
+        let file = sdcard.open_file_in_root_dir_for_writing(name)?;
+        let mut header = [0u8; HEADER_LEN];
+        header[0..4].copy_from_slice(&RECORDING_MAGIC);
+        header[4..6].copy_from_slice(&RECORDING_VERSION.to_le_bytes());
+        if let Err(e) = sdcard.write_file_in_root_dir_from_buffer(file, &header) {
+            sdcard.close_file(file)?;
+            return Err(e);
+        }
+        self.file = Some(file);
+        self.sample_count = 0;
+        self.read_count = 0;
+        Ok(())
+    }
+
+    /// Records all points of one read, does nothing while not recording
+    pub fn record<D: BlockDevice>(&mut self, sdcard: &mut SdcardManager<D>, time_ms: u64,
+                                  points: &TouchPoints) -> Result<(), SdcardError<D::Error>> {
+        let file = match self.file {
+            Some(file) => file,
+            None => return Ok(()),
+        };
+        let mut buffer = [0u8; RECORD_LEN * MAX_TOUCH_POINTS];
+        let len = encode_points(time_ms, self.read_count, points, &mut buffer);
+        if len == 0 {
+            return Ok(());
+        }
+        sdcard.write_file_in_root_dir_from_buffer(file, &buffer[..len])?;
+        self.sample_count += len / RECORD_LEN;
+        self.read_count = self.read_count.wrapping_add(1);
+        Ok(())
+    }
+
+    /// Closes the file, the recording is only complete after stop
+    pub fn stop<D: BlockDevice>(&mut self, sdcard: &mut SdcardManager<D>) -> Result<(), SdcardError<D::Error>> {
+        match self.file.take() {
+            Some(file) => sdcard.close_file(file),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Default for TouchRecorder {
+    fn default() -> Self {
+        TouchRecorder::new()
+    }
+}
+
+/// Replays a recording, every read returns the samples of the next recorded read.
+/// Reads after the end return no touch points
+pub struct TouchReplay {
+    samples: Vec<(u64, TouchSample)>,
+    reads: Vec<u32>,
+    position: usize,
+}
+
+impl TouchReplay {
+    /// Samples of one read share the timestamp, a repeated touch id starts the next read
+    pub fn new(samples: Vec<(u64, TouchSample)>) -> Self {
+        let reads = count_reads(&samples);
+        TouchReplay {
+            samples,
+            reads,
+            position: 0,
+        }
+    }
+
+    /// None if the header does not match, a truncated last record and the samples
+    /// after the first MAX_TOUCH_POINTS of one read are ignored
+    pub fn from_bytes(data: &[u8]) -> Option<Self> {
+        if data.len() < HEADER_LEN || data[0..4] != RECORDING_MAGIC {
+            return None;
+        }
+        let version = u16::from_le_bytes([data[4], data[5]]);
+        if version != RECORDING_VERSION {
+            return None;
+        }
+        let mut samples = Vec::new();
+        let mut reads = Vec::new();
+        let mut read_len = 0;
+        for (time_ms, read, sample) in data[HEADER_LEN..].chunks_exact(RECORD_LEN).filter_map(decode_sample) {
+            read_len = if reads.last() == Some(&read) { read_len + 1 } else { 1 };
+            if read_len > MAX_TOUCH_POINTS {
+                continue;
+            }
+            samples.push((time_ms, sample));
+            reads.push(read);
+        }
+        Some(TouchReplay {
+            samples,
+            reads,
+            position: 0,
+        })
+    }
+
+    /// Reads a recording from the root directory, None if it is not a recording
+    pub fn load<D, N>(sdcard: &mut SdcardManager<D>, name: N) -> Result<Option<Self>, SdcardError<D::Error>>
+        where D: BlockDevice, N: ToShortFileName {
+        let data = sdcard.load_root_dir_file_to_vec(name)?;
+        Ok(TouchReplay::from_bytes(&data))
+    }
+
+    pub fn get_samples(&self) -> &[(u64, TouchSample)] {
+        &self.samples
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.position >= self.samples.len()
+    }
+
+    /// Timestamp of the next read, None at the end
+    pub fn get_next_time_ms(&self) -> Option<u64> {
+        self.samples.get(self.position).map(|(time_ms, _)| *time_ms)
+    }
+
+    pub fn rewind(&mut self) {
+        self.position = 0;
+    }
+
+    fn next_points(&mut self) -> TouchPoints {
+        let mut points = [None; MAX_TOUCH_POINTS];
+        let read = match self.reads.get(self.position) {
+            Some(read) => *read,
+            None => return points,
+        };
+        // reads never have more than MAX_TOUCH_POINTS samples, see count_reads and from_bytes
+        for point in points.iter_mut() {
+            match self.samples.get(self.position) {
+                Some((_, sample)) if self.reads[self.position] == read => *point = Some(*sample),
+                _ => break,
+            }
+            self.position += 1;
+        }
+        points
+    }
+}
+
+impl TouchController for TouchReplay {
+    type Error = Infallible;
+
+    async fn read_touches(&mut self) -> Result<TouchPoints, Self::Error> {
+        Ok(self.next_points())
+    }
+}
+
+impl BlockingTouchController for TouchReplay {
+    type Error = Infallible;
+
+    fn read_touches(&mut self) -> Result<TouchPoints, Self::Error> {
+        Ok(self.next_points())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::String;
+
+    use embedded_graphics::geometry::{Point, Size};
+    use embedded_graphics::image::ImageRaw;
+    use embedded_graphics::mono_font::MonoFont;
+    use embedded_graphics::pixelcolor::Rgb565;
+    use embedded_graphics::primitives::PrimitiveStyle;
+    use embedded_graphics::text::{TextStyle, TextStyleBuilder};
+    use profont::PROFONT_12_POINT;
+
+    use crate::graphics::{Button, List, ListItem, Theme};
+    use crate::memory_display::MemoryDisplay;
+    use crate::touch::MappedTouchController;
+    use crate::touch_mapper::{Orientation, TouchPosMapper};
+
+    use super::*;
+
+    #[derive(Clone)]
+    struct Item(&'static str);
+
+    impl ListItem for Item {
+        fn get_text(&self) -> String {
+            String::from(self.0)
+        }
+
+        fn get_height(&self) -> u16 {
+            20
+        }
+
+        fn get_width(&self, display_width: u32) -> u32 {
+            display_width
+        }
+
+        fn get_font(&self) -> &MonoFont<'_> {
+            &PROFONT_12_POINT
+        }
+
+        fn get_text_style(&self) -> TextStyle {
+            TextStyleBuilder::new().build()
+        }
+    }
+
+    fn sample(phase: TouchPhase, x: u16, y: u16) -> TouchSample {
+        TouchSample {
+            id: 0,
+            x,
+            y,
+            pressure: Some(300),
+            phase,
+        }
+    }
+
+    fn recording(records: &[(u64, u32, TouchSample)]) -> Vec<u8> {
+        let mut data = Vec::from(RECORDING_MAGIC);
+        data.extend_from_slice(&RECORDING_VERSION.to_le_bytes());
+        for (time_ms, read, sample) in records {
+            data.extend_from_slice(&encode_sample(*time_ms, *read, sample));
+        }
+        data
+    }
+
+    #[test]
+    fn sample_encoding_round_trips() {
+        let with_pressure = TouchSample { id: 3, ..sample(TouchPhase::Move, 1234, 4000) };
+        let without_pressure = TouchSample { pressure: None, ..sample(TouchPhase::Up, 0, 65535) };
+        for (time_ms, read, sample) in [(u64::MAX, 7, with_pressure), (0, u32::MAX, without_pressure)] {
+            let decoded = decode_sample(&encode_sample(time_ms, read, &sample)).unwrap();
+            assert_eq!(decoded, (time_ms, read, sample));
+        }
+        let mut invalid_phase = encode_sample(0, 0, &with_pressure);
+        invalid_phase[13] = 3;
+        assert_eq!(decode_sample(&invalid_phase), None);
+    }
+
+    #[test]
+    fn reads_with_the_same_timestamp_are_replayed_separately() {
+        let data = recording(&[
+            (100, 0, sample(TouchPhase::Down, 10, 10)),
+            (100, 1, sample(TouchPhase::Up, 10, 10)),
+        ]);
+        let mut replay = TouchReplay::from_bytes(&data).unwrap();
+        assert_eq!(BlockingTouchController::read_touches(&mut replay).unwrap()[0].unwrap().phase, TouchPhase::Down);
+        assert_eq!(BlockingTouchController::read_touches(&mut replay).unwrap()[0].unwrap().phase, TouchPhase::Up);
+        assert!(replay.is_finished());
+
+        let mut replay = TouchReplay::new(replay.get_samples().to_vec());
+        assert_eq!(BlockingTouchController::read_touches(&mut replay).unwrap()[0].unwrap().phase, TouchPhase::Down);
+        assert!(!replay.is_finished());
+    }
+
+    #[test]
+    fn reads_are_limited_to_max_touch_points() {
+        let records: Vec<(u64, u32, TouchSample)> = (0..MAX_TOUCH_POINTS as u8 + 2)
+            .map(|id| (100, 0, TouchSample { id, ..sample(TouchPhase::Down, 10, 10) }))
+            .chain([(200, 1, sample(TouchPhase::Up, 10, 10))])
+            .collect();
+        let mut replay = TouchReplay::from_bytes(&recording(&records)).unwrap();
+        assert_eq!(replay.get_samples().len(), MAX_TOUCH_POINTS + 1);
+        let points = BlockingTouchController::read_touches(&mut replay).unwrap();
+        assert!(points.iter().enumerate().all(|(id, p)| p.unwrap().id == id as u8));
+        let points = BlockingTouchController::read_touches(&mut replay).unwrap();
+        assert_eq!(points[0].unwrap().phase, TouchPhase::Up);
+        assert!(replay.is_finished());
+
+        let samples: Vec<(u64, TouchSample)> = records.iter().map(|(time_ms, _, s)| (*time_ms, *s)).collect();
+        let mut replay = TouchReplay::new(samples);
+        assert!(BlockingTouchController::read_touches(&mut replay).unwrap().iter().all(|p| p.is_some()));
+        let points = BlockingTouchController::read_touches(&mut replay).unwrap();
+        assert_eq!(points.iter().flatten().map(|p| p.id).collect::<Vec<_>>(), [5, 6]);
+    }
+
+    #[test]
+    fn replay_selects_list_row_and_presses_button() {
+        let theme = Theme::new_dark_theme();
+        let mut display = MemoryDisplay::new(240, 320);
+        let items: Vec<Item> = ["WiFi", "Bluetooth", "Display", "Sound", "Storage"].iter().map(|t| Item(t)).collect();
+        let mut list = List::new(&items, Point::zero(), Size::new(240, 200), &theme);
+        let image_data = [0u8; 4 * 4 * 2];
+        let image = ImageRaw::<Rgb565>::new(&image_data, 4);
+        let button = Button::new(&image, Point::new(0, 220));
+        let mapper = TouchPosMapper::new(240, 320, (0, 4095), (0, 4095));
+
+        // record the mapped output of the raw 12 bit samples, row 3 at y 70 and the button at (45, 245)
+        let raw = TouchReplay::new(Vec::from([
+            (100, sample(TouchPhase::Down, 2048, 896)),
+            (100, sample(TouchPhase::Up, 2048, 896)),
+            (200, sample(TouchPhase::Down, 768, 3135)),
+            (250, sample(TouchPhase::Up, 768, 3135)),
+        ]));
+        let mut mapped = MappedTouchController::new(raw, mapper, Orientation::Deg0);
+        let mut data = recording(&[]);
+        let mut read = 0;
+        while let Some(time_ms) = mapped.controller().get_next_time_ms() {
+            let points = BlockingTouchController::read_touches(&mut mapped).unwrap();
+            let mut buffer = [0u8; RECORD_LEN * MAX_TOUCH_POINTS];
+            let len = encode_points(time_ms, read, &points, &mut buffer);
+            data.extend_from_slice(&buffer[..len]);
+            read += 1;
+        }
+
+        let mut replay = TouchReplay::from_bytes(&data).unwrap();
+        let mut reads = 0;
+        let mut button_presses = 0;
+        while !replay.is_finished() {
+            let points = BlockingTouchController::read_touches(&mut replay).unwrap();
+            reads += 1;
+            let touch = match points[0] {
+                Some(touch) if touch.phase == TouchPhase::Down => touch,
+                _ => continue,
+            };
+            let pos = Point::new(touch.x as i32, touch.y as i32);
+            if list.get_bounding_box().contains(pos) {
+                list.select_at_pos(&mut display, pos).unwrap();
+            } else if button.get_bounding_box().contains(pos) {
+                button.draw(&mut display, PrimitiveStyle::with_fill(theme.button_background_color)).unwrap();
+                button_presses += 1;
+            }
+        }
+
+        assert_eq!(reads, 4);
+        assert_eq!(list.get_selected_index(), 3);
+        assert_eq!(button_presses, 1);
+        assert_eq!(display.get_pixel(Point::new(200, 3 * 20 + 2)), Some(theme.highlight_color));
+        assert_eq!(display.get_pixel(Point::new(200, 2 * 20 + 2)), Some(theme.screen_background_color));
+        assert_eq!(display.get_pixel(Point::new(20, 245)), Some(theme.button_background_color));
+        assert_eq!(display.get_pixel(Point::new(45, 245)), Some(Rgb565::new(0, 0, 0)));
+    }
+}
@@ -1,80 +1,229 @@
-use core::fmt::Debug;
-
+use embedded_hal_async::delay::DelayNs;
+use embedded_hal_async::digital::Wait;
 use embedded_hal_async::i2c::I2c;
 
+use crate::resistive_touch;
+use crate::resistive_touch::{AdcResolution, MAX_OVERSAMPLING, PenEvent, PenIrqDriver, PenIrqError, PenStateMachine, ResistiveSample,
+                             ResistiveTouchConfig};
+
 pub struct Tsc2007<I2C> {
     i2c: I2C,
+    addr: u8,
     buf: [u8; 2],
     cmd: [u8; 1],
+    config: ResistiveTouchConfig,
+    pen: PenStateMachine,
 }
 
 pub const TSC2007_ADDR: u8 = 0x48;
-const TSC2007_MEASURE_TEMP0: u8 = 0;
-const TSC2007_MEASURE_AUX: u8 = 2;
-const TSC2007_MEASURE_TEMP1: u8 = 4;
+
+/// I2C address for the A1 and A0 pin levels
+pub const fn tsc2007_addr(a1: bool, a0: bool) -> u8 {
+    TSC2007_ADDR | (a1 as u8) << 1 | a0 as u8
+}
+
+pub(crate) const TSC2007_MEASURE_TEMP0: u8 = 0;
+pub(crate) const TSC2007_MEASURE_AUX: u8 = 2;
+pub(crate) const TSC2007_MEASURE_TEMP1: u8 = 4;
 const TSC2007_ACTIVATE_X: u8 = 8;
 const TSC2007_ACTIVATE_Y: u8 = 9;
 const TSC2007_ACTIVATE_YPLUS_X: u8 = 10;
 const TSC2007_SETUP_COMMAND: u8 = 11;
-const TSC2007_MEASURE_X: u8 = 12;
-const TSC2007_MEASURE_Y: u8 = 13;
-const TSC2007_MEASURE_Z1: u8 = 14;
-const TSC2007_MEASURE_Z2: u8 = 15;
+pub(crate) const TSC2007_MEASURE_X: u8 = 12;
+pub(crate) const TSC2007_MEASURE_Y: u8 = 13;
+pub(crate) const TSC2007_MEASURE_Z1: u8 = 14;
+pub(crate) const TSC2007_MEASURE_Z2: u8 = 15;
 
-const TSC2007_POWERDOWN_IRQON: u8 = 0;
-const TSC2007_ADON_IRQOFF: u8 = 1;
+pub(crate) const TSC2007_POWERDOWN_IRQON: u8 = 0;
+pub(crate) const TSC2007_ADON_IRQOFF: u8 = 1;
 const TSC2007_ADOFF_IRQON: u8 = 2;
 
-const TSC2007_ADC_12BIT: u8 = 0;
-const TSC2007_ADC_8BIT: u8 = 1;
+pub(crate) const TSC2007_ADC_12BIT: u8 = 0;
+pub(crate) const TSC2007_ADC_8BIT: u8 = 1;
 
 pub const TS_MINX: u16 = 550;
 pub const TS_MINY: u16 = 350;
 pub const TS_MAXX: u16 = 3600;
 pub const TS_MAXY: u16 = 3700;
+/// Z1 at 12 bit resolution, scaled for 8 bit conversions
 pub const TS_MIN_PRESSURE: u16 = 100;
 
-impl<I2C: I2c> Tsc2007<I2C> {
-    pub fn new(i2c: I2C) -> Self {
-        Tsc2007 {
-            i2c,
-            buf: [0u8; 2],
-            cmd: [0u8; 1],
-        }
+pub(crate) fn command_byte(function: u8, power: u8, resolution: u8) -> u8 {
+    let mut cmd = (function & 0x0F) << 4;
+    cmd |= (power & 0x03) << 2;
+    cmd |= (resolution & 0x01) << 1;
+    cmd
+}
+
+pub(crate) fn adc_to_voltage(value: u16, vref: f32) -> f32 {
+    value as f32 * vref / 4096.0
+}
+
+/// T = 2.573 K/mV * (V(TEMP1) - V(TEMP0)) - 273 K
+pub(crate) fn temperature_from_adc(temp0: u16, temp1: u16, vref: f32) -> f32 {
+    let delta_mv = (adc_to_voltage(temp1, vref) - adc_to_voltage(temp0, vref)) * 1000.0;
+    2.573 * delta_mv - 273.0
+}
+
+pub(crate) fn resolution_bit(resolution: AdcResolution) -> u8 {
+    match resolution {
+        AdcResolution::Bits8 => TSC2007_ADC_8BIT,
+        AdcResolution::Bits12 => TSC2007_ADC_12BIT,
     }
+}
 
-    pub async fn init_irq(&mut self) -> Result<(u16), I2C::Error> {
-        self.command(TSC2007_MEASURE_TEMP0, TSC2007_POWERDOWN_IRQON, TSC2007_ADC_12BIT).await
+pub(crate) fn decode_result(buf: &[u8; 2], resolution: u8) -> u16 {
+    if resolution == TSC2007_ADC_8BIT {
+        return buf[0] as u16;
     }
+    (buf[0] as u16) << 4 | (buf[1] as u16) >> 4
+}
 
-    async fn command(&mut self, function: u8, power: u8, resolution: u8) -> Result<u16, I2C::Error> {
-        self.cmd[0] = (function & 0x0F) << 4;
-        self.cmd[0] |= (power & 0x03) << 2;
-        self.cmd[0] |= (resolution & 0x01) << 1;
+/// Methods of Tsc2007, shared by the async and the blocking driver. Invoked with
+/// `async; await` here and without arguments in tsc2007_blocking
+macro_rules! tsc2007_driver {
+    ($($async:ident)?; $($await:ident)?) => {
+        impl<I2C: I2c> Tsc2007<I2C> {
+            pub fn new(i2c: I2C) -> Self {
+                Tsc2007::new_with_config(i2c, ResistiveTouchConfig::default())
+            }
 
-        return match self.i2c.write_read(TSC2007_ADDR, &self.cmd, &mut self.buf).await {
-            Ok(()) => {
-                let result = (self.buf[0] as u16) << 4 | (self.buf[1] as u16) >> 4;
-                Ok(result)
+            pub fn new_with_addr(i2c: I2C, addr: u8) -> Self {
+                let mut tsc2007 = Tsc2007::new(i2c);
+                tsc2007.addr = addr;
+                tsc2007
             }
-            Err(e) => {
-                Err(e)
+
+            pub fn new_with_config(i2c: I2C, config: ResistiveTouchConfig) -> Self {
+                Tsc2007 {
+                    i2c,
+                    addr: TSC2007_ADDR,
+                    buf: [0u8; 2],
+                    cmd: [0u8; 1],
+                    config,
+                    pen: PenStateMachine::new(),
+                }
+            }
+
+            pub fn get_config(&self) -> ResistiveTouchConfig {
+                self.config
+            }
+
+            pub fn set_config(&mut self, config: ResistiveTouchConfig) {
+                self.config = config;
             }
-        };
-    }
 
-    pub async fn touched(&mut self) -> bool {
-        if let Ok(point) = self.touch().await {
-            return point.2 > TS_MIN_PRESSURE;
+            pub $($async)? fn init_irq(&mut self) -> Result<u16, I2C::Error> {
+                self.command(TSC2007_MEASURE_TEMP0, TSC2007_POWERDOWN_IRQON, TSC2007_ADC_12BIT)$(.$await)?
+            }
+
+            $($async)? fn command(&mut self, function: u8, power: u8, resolution: u8) -> Result<u16, I2C::Error> {
+                self.cmd[0] = command_byte(function, power, resolution);
+                self.i2c.write_read(self.addr, &self.cmd, &mut self.buf)$(.$await)??;
+                Ok(decode_result(&self.buf, resolution))
+            }
+
+            pub $($async)? fn touched(&mut self) -> bool {
+                if let Ok(point) = self.touch()$(.$await)? {
+                    return point.2 > self.config.resolution.from_12bit(TS_MIN_PRESSURE);
+                }
+                false
+            }
+
+            pub $($async)? fn touch(&mut self) -> Result<(u16, u16, u16), I2C::Error> {
+                let resolution = resolution_bit(self.config.resolution);
+                let x = self.command(TSC2007_MEASURE_X, TSC2007_ADON_IRQOFF, resolution)$(.$await)??;
+                let y = self.command(TSC2007_MEASURE_Y, TSC2007_ADON_IRQOFF, resolution)$(.$await)??;
+                let z = self.command(TSC2007_MEASURE_Z1, TSC2007_ADON_IRQOFF, resolution)$(.$await)??;
+                self.command(TSC2007_MEASURE_TEMP0, TSC2007_POWERDOWN_IRQON, resolution)$(.$await)??;
+                Ok((x, y, z))
+            }
+
+            $($async)? fn measure(&mut self, function: u8, resolution: u8) -> Result<u16, I2C::Error> {
+                let mut samples = [0u16; MAX_OVERSAMPLING];
+                let count = self.config.get_oversampling();
+                for sample in samples.iter_mut().take(count) {
+                    *sample = self.command(function, TSC2007_ADON_IRQOFF, resolution)$(.$await)??;
+                }
+                Ok(self.config.filter.apply(&mut samples[..count]))
+            }
+
+            /// Oversampled and filtered X, Y, Z1 and Z2 with the touch resistance
+            pub $($async)? fn read_sample(&mut self) -> Result<ResistiveSample, I2C::Error> {
+                let resolution = resolution_bit(self.config.resolution);
+                let x = self.measure(TSC2007_MEASURE_X, resolution)$(.$await)??;
+                let y = self.measure(TSC2007_MEASURE_Y, resolution)$(.$await)??;
+                let z1 = self.measure(TSC2007_MEASURE_Z1, resolution)$(.$await)??;
+                let z2 = self.measure(TSC2007_MEASURE_Z2, resolution)$(.$await)??;
+                self.command(TSC2007_MEASURE_TEMP0, TSC2007_POWERDOWN_IRQON, resolution)$(.$await)??;
+                Ok(ResistiveSample::new(x, y, z1, z2, &self.config))
+            }
+
+            /// Reads a sample and runs the pen state machine, call this periodically
+            pub $($async)? fn poll(&mut self) -> Result<Option<PenEvent>, I2C::Error> {
+                let sample = self.read_sample()$(.$await)??;
+                Ok(self.pen.update(sample, &self.config))
+            }
+
+            /// Raw 12 bit AUX conversion
+            pub $($async)? fn read_aux(&mut self) -> Result<u16, I2C::Error> {
+                let aux = self.measure(TSC2007_MEASURE_AUX, TSC2007_ADC_12BIT)$(.$await)??;
+                self.command(TSC2007_MEASURE_TEMP0, TSC2007_POWERDOWN_IRQON, TSC2007_ADC_12BIT)$(.$await)??;
+                Ok(aux)
+            }
+
+            /// AUX input voltage, vref is the reference voltage of the chip in volts
+            pub $($async)? fn read_aux_voltage(&mut self, vref: f32) -> Result<f32, I2C::Error> {
+                let aux = self.read_aux()$(.$await)??;
+                Ok(adc_to_voltage(aux, vref))
+            }
+
+            /// Die temperature in degrees celsius with the two measurement method,
+            /// vref is the reference voltage of the chip in volts
+            pub $($async)? fn read_temperature(&mut self, vref: f32) -> Result<f32, I2C::Error> {
+                let temp0 = self.measure(TSC2007_MEASURE_TEMP0, TSC2007_ADC_12BIT)$(.$await)??;
+                let temp1 = self.measure(TSC2007_MEASURE_TEMP1, TSC2007_ADC_12BIT)$(.$await)??;
+                self.command(TSC2007_MEASURE_TEMP0, TSC2007_POWERDOWN_IRQON, TSC2007_ADC_12BIT)$(.$await)??;
+                Ok(temperature_from_adc(temp0, temp1, vref))
+            }
+
+            pub fn is_pen_down(&self) -> bool {
+                self.pen.is_pen_down()
+            }
         }
-        false
+    };
+}
+
+pub(crate) use tsc2007_driver;
+
+tsc2007_driver!(async; await);
+
+impl<I2C: I2c> Tsc2007<I2C> {
+    /// Waits for pen down on PENIRQ and then samples every interval_ms until pen up,
+    /// every pen event is passed to on_event. Returns after the pen up event
+    pub async fn wait_for_touch<P, D, F>(&mut self, penirq: &mut P, delay: &mut D, interval_ms: u32,
+                                         on_event: F) -> Result<(), PenIrqError<I2C::Error, P::Error>>
+        where P: Wait, D: DelayNs, F: FnMut(PenEvent) {
+        resistive_touch::wait_for_touch(self, penirq, delay, interval_ms, on_event).await
+    }
+}
+
+impl<I2C: I2c> PenIrqDriver for Tsc2007<I2C> {
+    type Error = I2C::Error;
+
+    async fn init_irq(&mut self) -> Result<u16, Self::Error> {
+        self.init_irq().await
+    }
+
+    async fn poll(&mut self) -> Result<Option<PenEvent>, Self::Error> {
+        self.poll().await
     }
 
-    pub async fn touch(&mut self) -> Result<(u16, u16, u16), I2C::Error> {
-        let x = self.command(TSC2007_MEASURE_X, TSC2007_ADON_IRQOFF, TSC2007_ADC_12BIT).await?;
-        let y = self.command(TSC2007_MEASURE_Y, TSC2007_ADON_IRQOFF, TSC2007_ADC_12BIT).await?;
-        let z = self.command(TSC2007_MEASURE_Z1, TSC2007_ADON_IRQOFF, TSC2007_ADC_12BIT).await?;
-        self.command(TSC2007_MEASURE_TEMP0, TSC2007_POWERDOWN_IRQON, TSC2007_ADC_12BIT).await?;
-        Ok((x, y, z))
+    fn is_pen_down(&self) -> bool {
+        self.pen.is_pen_down()
     }
-}
\ No newline at end of file
+
+    fn get_config(&self) -> ResistiveTouchConfig {
+        self.config
+    }
+}
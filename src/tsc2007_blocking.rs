@@ -0,0 +1,60 @@
+use embedded_hal::i2c::I2c;
+
+use crate::resistive_touch::{MAX_OVERSAMPLING, PenEvent, PenStateMachine, ResistiveSample, ResistiveTouchConfig};
+use crate::tsc2007::{adc_to_voltage, command_byte, decode_result, resolution_bit, temperature_from_adc, TS_MIN_PRESSURE,
+                     TSC2007_ADC_12BIT, TSC2007_ADDR, TSC2007_ADON_IRQOFF, TSC2007_MEASURE_AUX, TSC2007_MEASURE_TEMP0,
+                     TSC2007_MEASURE_TEMP1, TSC2007_MEASURE_X, TSC2007_MEASURE_Y, TSC2007_MEASURE_Z1, TSC2007_MEASURE_Z2,
+                     TSC2007_POWERDOWN_IRQON, tsc2007_driver};
+
+/// Blocking variant of tsc2007::Tsc2007, the methods are generated from the same tsc2007_driver macro
+pub struct Tsc2007<I2C> {
+    i2c: I2C,
+    addr: u8,
+    buf: [u8; 2],
+    cmd: [u8; 1],
+    config: ResistiveTouchConfig,
+    pen: PenStateMachine,
+}
+
+tsc2007_driver!(;);
+
+#[cfg(test)]
+mod tests {
+    use crate::mock_i2c::Tsc2007Model;
+    use crate::resistive_touch::AdcResolution;
+
+    use super::*;
+
+    fn tsc2007_with_z1(resolution: AdcResolution, z1: u16) -> Tsc2007<crate::mock_i2c::ModelI2c<Tsc2007Model>> {
+        let mut bus = Tsc2007Model::new_bus();
+        bus.model().set_touch(2000, 1500, z1, 3000);
+        let config = ResistiveTouchConfig {
+            resolution,
+            ..ResistiveTouchConfig::default()
+        };
+        Tsc2007::new_with_config(bus, config)
+    }
+
+    #[test]
+    fn touched_threshold_at_12_bit() {
+        assert!(!tsc2007_with_z1(AdcResolution::Bits12, TS_MIN_PRESSURE).touched());
+        assert!(tsc2007_with_z1(AdcResolution::Bits12, TS_MIN_PRESSURE + 1).touched());
+    }
+
+    #[test]
+    fn touched_threshold_is_scaled_at_8_bit() {
+        // 8 bit conversions drop the low 4 bits, the threshold is 100 >> 4 = 6, so Z1 111
+        // is above the 12 bit threshold but reads as 6
+        assert!(!tsc2007_with_z1(AdcResolution::Bits8, (7 << 4) - 1).touched());
+        assert!(tsc2007_with_z1(AdcResolution::Bits8, 7 << 4).touched());
+        assert!(tsc2007_with_z1(AdcResolution::Bits8, 0xFFF).touched());
+    }
+
+    #[test]
+    fn touch_enables_penirq_after_the_conversions() {
+        let mut tsc2007 = tsc2007_with_z1(AdcResolution::Bits12, 400);
+        assert_eq!(tsc2007.touch().unwrap(), (2000, 1500, 400));
+        assert!(tsc2007.i2c.model().is_irq_enabled());
+        assert_eq!(tsc2007.i2c.model().get_conversion_count(), 4);
+    }
+}
@@ -0,0 +1,275 @@
+use embedded_hal_async::delay::DelayNs;
+use embedded_hal_async::digital::Wait;
+use embedded_hal_async::spi::SpiDevice;
+
+use crate::resistive_touch;
+use crate::resistive_touch::{AdcResolution, MAX_OVERSAMPLING, PenEvent, PenIrqDriver, PenIrqError, PenStateMachine, ResistiveSample,
+                             ResistiveTouchConfig};
+use crate::tsc2007::{adc_to_voltage, temperature_from_adc};
+
+/// XPT2046 / ADS7846 resistive touch controller. The SpiDevice owns the chip select, so the bus
+/// can be shared with the display and the SD card, e.g. with embedded-hal-bus. The device
+/// must be configured for SPI mode 0 and at most 2 MHz
+pub struct Xpt2046<SPI> {
+    spi: SPI,
+    buf: [u8; 3],
+    cmd: [u8; 3],
+    config: ResistiveTouchConfig,
+    pen: PenStateMachine,
+}
+
+const XPT2046_START: u8 = 0x80;
+
+const XPT2046_MEASURE_TEMP0: u8 = 0;
+const XPT2046_MEASURE_Y: u8 = 1;
+const XPT2046_MEASURE_BATTERY: u8 = 2;
+const XPT2046_MEASURE_Z1: u8 = 3;
+const XPT2046_MEASURE_Z2: u8 = 4;
+const XPT2046_MEASURE_X: u8 = 5;
+const XPT2046_MEASURE_AUX: u8 = 6;
+const XPT2046_MEASURE_TEMP1: u8 = 7;
+
+const XPT2046_ADC_12BIT: u8 = 0;
+const XPT2046_ADC_8BIT: u8 = 1;
+
+const XPT2046_DIFFERENTIAL: u8 = 0;
+const XPT2046_SINGLE_ENDED: u8 = 1;
+
+const XPT2046_POWERDOWN_IRQON: u8 = 0;
+const XPT2046_ADON_IRQOFF: u8 = 1;
+/// internal 2.5 V reference and ADC on, used for the single ended inputs
+const XPT2046_REFON_ADON: u8 = 3;
+
+/// Reference voltage of the internal reference
+pub const XPT2046_INTERNAL_VREF: f32 = 2.5;
+
+fn command_byte(channel: u8, resolution: u8, mode: u8, power: u8) -> u8 {
+    XPT2046_START | (channel & 0x07) << 4 | (resolution & 0x01) << 3 | (mode & 0x01) << 2 | (power & 0x03)
+}
+
+/// The result starts after the busy bit, one clock after the command byte
+fn decode_result(buf: &[u8; 3], resolution: u8) -> u16 {
+    let word = (buf[1] as u16) << 8 | buf[2] as u16;
+    if resolution == XPT2046_ADC_8BIT {
+        return (word >> 7) & 0xFF;
+    }
+    (word >> 3) & 0xFFF
+}
+
+fn resolution_bit(resolution: AdcResolution) -> u8 {
+    match resolution {
+        AdcResolution::Bits8 => XPT2046_ADC_8BIT,
+        AdcResolution::Bits12 => XPT2046_ADC_12BIT,
+    }
+}
+
+impl<SPI: SpiDevice> Xpt2046<SPI> {
+    pub fn new(spi: SPI) -> Self {
+        Xpt2046::new_with_config(spi, ResistiveTouchConfig::default())
+    }
+
+    pub fn new_with_config(spi: SPI, config: ResistiveTouchConfig) -> Self {
+        Xpt2046 {
+            spi,
+            buf: [0u8; 3],
+            cmd: [0u8; 3],
+            config,
+            pen: PenStateMachine::new(),
+        }
+    }
+
+    pub fn get_config(&self) -> ResistiveTouchConfig {
+        self.config
+    }
+
+    pub fn set_config(&mut self, config: ResistiveTouchConfig) {
+        self.config = config;
+    }
+
+    /// Powers down the chip between conversions and enables PENIRQ
+    pub async fn init_irq(&mut self) -> Result<u16, SPI::Error> {
+        self.command(XPT2046_MEASURE_X, XPT2046_ADC_12BIT, XPT2046_DIFFERENTIAL, XPT2046_POWERDOWN_IRQON).await
+    }
+
+    async fn command(&mut self, channel: u8, resolution: u8, mode: u8, power: u8) -> Result<u16, SPI::Error> {
+        self.cmd[0] = command_byte(channel, resolution, mode, power);
+        self.spi.transfer(&mut self.buf, &self.cmd).await?;
+        Ok(decode_result(&self.buf, resolution))
+    }
+
+    async fn measure(&mut self, channel: u8, resolution: u8, mode: u8, power: u8) -> Result<u16, SPI::Error> {
+        let mut samples = [0u16; MAX_OVERSAMPLING];
+        let count = self.config.get_oversampling();
+        for sample in samples.iter_mut().take(count) {
+            *sample = self.command(channel, resolution, mode, power).await?;
+        }
+        Ok(self.config.filter.apply(&mut samples[..count]))
+    }
+
+    async fn measure_touch(&mut self, channel: u8) -> Result<u16, SPI::Error> {
+        let resolution = resolution_bit(self.config.resolution);
+        self.measure(channel, resolution, XPT2046_DIFFERENTIAL, XPT2046_ADON_IRQOFF).await
+    }
+
+    async fn measure_single_ended(&mut self, channel: u8) -> Result<u16, SPI::Error> {
+        let value = self.measure(channel, XPT2046_ADC_12BIT, XPT2046_SINGLE_ENDED, XPT2046_REFON_ADON).await?;
+        self.init_irq().await?;
+        Ok(value)
+    }
+
+    /// Oversampled and filtered X, Y, Z1 and Z2 with the touch resistance
+    pub async fn read_sample(&mut self) -> Result<ResistiveSample, SPI::Error> {
+        let x = self.measure_touch(XPT2046_MEASURE_X).await?;
+        let y = self.measure_touch(XPT2046_MEASURE_Y).await?;
+        let z1 = self.measure_touch(XPT2046_MEASURE_Z1).await?;
+        let z2 = self.measure_touch(XPT2046_MEASURE_Z2).await?;
+        self.init_irq().await?;
+        Ok(ResistiveSample::new(x, y, z1, z2, &self.config))
+    }
+
+    /// Reads a sample and runs the pen state machine, call this periodically
+    pub async fn poll(&mut self) -> Result<Option<PenEvent>, SPI::Error> {
+        let sample = self.read_sample().await?;
+        Ok(self.pen.update(sample, &self.config))
+    }
+
+    pub fn is_pen_down(&self) -> bool {
+        self.pen.is_pen_down()
+    }
+
+    /// Waits for pen down on PENIRQ and then samples every interval_ms until pen up,
+    /// every pen event is passed to on_event. Returns after the pen up event
+    pub async fn wait_for_touch<P, D, F>(&mut self, penirq: &mut P, delay: &mut D, interval_ms: u32,
+                                         on_event: F) -> Result<(), PenIrqError<SPI::Error, P::Error>>
+        where P: Wait, D: DelayNs, F: FnMut(PenEvent) {
+        resistive_touch::wait_for_touch(self, penirq, delay, interval_ms, on_event).await
+    }
+
+    /// Raw 12 bit AUX conversion against the internal reference
+    pub async fn read_aux(&mut self) -> Result<u16, SPI::Error> {
+        self.measure_single_ended(XPT2046_MEASURE_AUX).await
+    }
+
+    pub async fn read_aux_voltage(&mut self) -> Result<f32, SPI::Error> {
+        let aux = self.read_aux().await?;
+        Ok(adc_to_voltage(aux, XPT2046_INTERNAL_VREF))
+    }
+
+    /// VBAT voltage, the chip measures a quarter of it
+    pub async fn read_battery_voltage(&mut self) -> Result<f32, SPI::Error> {
+        let battery = self.measure_single_ended(XPT2046_MEASURE_BATTERY).await?;
+        Ok(adc_to_voltage(battery, XPT2046_INTERNAL_VREF) * 4.0)
+    }
+
+    /// Die temperature in degrees celsius with the two measurement method
+    pub async fn read_temperature(&mut self) -> Result<f32, SPI::Error> {
+        let temp0 = self.measure_single_ended(XPT2046_MEASURE_TEMP0).await?;
+        let temp1 = self.measure_single_ended(XPT2046_MEASURE_TEMP1).await?;
+        Ok(temperature_from_adc(temp0, temp1, XPT2046_INTERNAL_VREF))
+    }
+}
+
+impl<SPI: SpiDevice> PenIrqDriver for Xpt2046<SPI> {
+    type Error = SPI::Error;
+
+    async fn init_irq(&mut self) -> Result<u16, Self::Error> {
+        self.init_irq().await
+    }
+
+    async fn poll(&mut self) -> Result<Option<PenEvent>, Self::Error> {
+        self.poll().await
+    }
+
+    fn is_pen_down(&self) -> bool {
+        self.pen.is_pen_down()
+    }
+
+    fn get_config(&self) -> ResistiveTouchConfig {
+        self.config
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate alloc;
+
+    use alloc::vec::Vec;
+    use core::convert::Infallible;
+
+    use embedded_hal_async::spi::{ErrorType, Operation};
+
+    use crate::mock_i2c::block_on;
+
+    use super::*;
+
+    /// Answers every command with the conversion result of its channel
+    struct Xpt2046Model {
+        values: [u16; 8],
+        commands: Vec<u8>,
+    }
+
+    impl ErrorType for Xpt2046Model {
+        type Error = Infallible;
+    }
+
+    impl SpiDevice for Xpt2046Model {
+        async fn transaction(&mut self, operations: &mut [Operation<'_, u8>]) -> Result<(), Self::Error> {
+            for operation in operations.iter_mut() {
+                if let Operation::Transfer(read, write) = operation {
+                    let command = write[0];
+                    self.commands.push(command);
+                    let value = self.values[((command >> 4) & 0x07) as usize];
+                    let word = if (command >> 3) & 0x01 == XPT2046_ADC_8BIT { (value >> 4) << 7 } else { value << 3 };
+                    read.copy_from_slice(&[0, (word >> 8) as u8, word as u8]);
+                }
+            }
+            Ok(())
+        }
+    }
+
+    fn xpt2046(resolution: AdcResolution) -> Xpt2046<Xpt2046Model> {
+        let mut values = [0u16; 8];
+        values[XPT2046_MEASURE_X as usize] = 0xABC;
+        values[XPT2046_MEASURE_Y as usize] = 0x123;
+        values[XPT2046_MEASURE_Z1 as usize] = 0x400;
+        values[XPT2046_MEASURE_Z2 as usize] = 0xFFF;
+        let config = ResistiveTouchConfig { resolution, ..ResistiveTouchConfig::default() };
+        Xpt2046::new_with_config(Xpt2046Model { values, commands: Vec::new() }, config)
+    }
+
+    #[test]
+    fn channel_commands() {
+        let command = |channel| command_byte(channel, XPT2046_ADC_12BIT, XPT2046_DIFFERENTIAL, XPT2046_ADON_IRQOFF);
+        assert_eq!(command(XPT2046_MEASURE_X), 0xD1);
+        assert_eq!(command(XPT2046_MEASURE_Y), 0x91);
+        assert_eq!(command(XPT2046_MEASURE_Z1), 0xB1);
+        assert_eq!(command(XPT2046_MEASURE_Z2), 0xC1);
+        assert_eq!(command_byte(XPT2046_MEASURE_X, XPT2046_ADC_8BIT, XPT2046_DIFFERENTIAL, XPT2046_POWERDOWN_IRQON), 0xD8);
+        assert_eq!(command_byte(XPT2046_MEASURE_BATTERY, XPT2046_ADC_12BIT, XPT2046_SINGLE_ENDED, XPT2046_REFON_ADON), 0xA7);
+    }
+
+    #[test]
+    fn decodes_results_after_the_busy_bit() {
+        assert_eq!(decode_result(&[0xFF, 0x7F, 0xF8], XPT2046_ADC_12BIT), 0xFFF);
+        assert_eq!(decode_result(&[0x00, 0x55, 0x60], XPT2046_ADC_12BIT), 0xAAC);
+        assert_eq!(decode_result(&[0xFF, 0x7F, 0x80], XPT2046_ADC_8BIT), 0xFF);
+        assert_eq!(decode_result(&[0x00, 0x2A, 0x80], XPT2046_ADC_8BIT), 0x55);
+    }
+
+    #[test]
+    fn read_sample_at_12_bit() {
+        let mut xpt2046 = xpt2046(AdcResolution::Bits12);
+        let sample = block_on(xpt2046.read_sample()).unwrap();
+        assert_eq!((sample.x, sample.y, sample.z1, sample.z2), (0xABC, 0x123, 0x400, 0xFFF));
+        assert_eq!(xpt2046.spi.commands, [0xD1, 0x91, 0xB1, 0xC1, 0xD0]);
+    }
+
+    #[test]
+    fn read_sample_at_8_bit() {
+        let mut xpt2046 = xpt2046(AdcResolution::Bits8);
+        let sample = block_on(xpt2046.read_sample()).unwrap();
+        assert_eq!((sample.x, sample.y, sample.z1, sample.z2), (0xAB, 0x12, 0x40, 0xFF));
+        assert!(sample.is_touched(&xpt2046.get_config()));
+        assert_eq!(xpt2046.spi.commands, [0xD9, 0x99, 0xB9, 0xC9, 0xD0]);
+    }
+}